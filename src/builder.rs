@@ -0,0 +1,172 @@
+use crate::{
+    endpoint::{Endpoint, ToEndpoint},
+    error::Result,
+    socket::Socket,
+};
+
+// configures a socket and applies each option at the point it has to be set relative to
+// bind/connect: address reuse and buffer sizes before, non-blocking mode after
+#[derive(Debug, Clone, Default)]
+pub struct SocketBuilder {
+    reuse_address: bool,
+    reuse_port: bool,
+    nodelay: bool,
+    nonblocking: bool,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>,
+}
+
+impl SocketBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reuse_address(mut self, enable: bool) -> Self {
+        self.reuse_address = enable;
+        self
+    }
+
+    pub fn reuse_port(mut self, enable: bool) -> Self {
+        self.reuse_port = enable;
+        self
+    }
+
+    pub fn nodelay(mut self, enable: bool) -> Self {
+        self.nodelay = enable;
+        self
+    }
+
+    pub fn nonblocking(mut self, enable: bool) -> Self {
+        self.nonblocking = enable;
+        self
+    }
+
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    pub fn bind(&self, addr: impl ToEndpoint) -> Result<Socket> {
+        let addr = addr.to_endpoint()?;
+        let mut socket = self.open(&addr)?;
+        socket.bind(&addr)?;
+        self.finish(socket)
+    }
+
+    // binds and starts listening, the usual way to build a server socket
+    pub fn listen(&self, addr: impl ToEndpoint, backlog: i32) -> Result<Socket> {
+        let addr = addr.to_endpoint()?;
+        let mut socket = self.open(&addr)?;
+        socket.bind(&addr)?;
+        socket.listen(backlog)?;
+        self.finish(socket)
+    }
+
+    pub fn connect(&self, addr: impl ToEndpoint) -> Result<Socket> {
+        let addr = addr.to_endpoint()?;
+        let mut socket = self.open(&addr)?;
+        socket.connect(&addr)?;
+        self.finish(socket)
+    }
+
+    // creates a stream socket of the family addr belongs to and applies the pre-bind/connect
+    // options, TCP-only ones like nodelay are skipped for Unix domain sockets
+    fn open(&self, addr: &Endpoint) -> Result<Socket> {
+        let socket = match addr {
+            Endpoint::V4(..) => Socket::new()?,
+            Endpoint::V6(..) => Socket::new_v6()?,
+            Endpoint::Unix(_) => Socket::new_unix()?,
+        };
+
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        if self.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+        if self.nodelay && !matches!(addr, Endpoint::Unix(_)) {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        Ok(socket)
+    }
+
+    // non-blocking mode is applied last so bind/connect above still block
+    fn finish(&self, socket: Socket) -> Result<Socket> {
+        if self.nonblocking {
+            socket.set_nonblocking(true)?;
+        }
+        Ok(socket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SocketError;
+
+    #[test]
+    fn test_builder_creates_listening_socket() {
+        let listener = SocketBuilder::new()
+            .reuse_address(true)
+            .listen(("127.0.0.1", 0), 16)
+            .expect("Failed to build listening socket");
+
+        assert!(listener.is_listening());
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let client = SocketBuilder::new()
+            .nodelay(true)
+            .connect(("127.0.0.1", port))
+            .expect("Failed to build connected socket");
+
+        assert!(client.is_connected());
+        assert!(client.nodelay().expect("Failed to read TCP_NODELAY"));
+    }
+
+    #[test]
+    fn test_builder_skips_nodelay_for_unix_sockets() {
+        let path =
+            std::env::temp_dir().join(format!("bs_builder_nodelay_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let addr = Endpoint::Unix(path.clone());
+
+        let listener = SocketBuilder::new()
+            .nodelay(true)
+            .listen(&addr, 1)
+            .expect("Failed to build listening socket");
+        let client = SocketBuilder::new()
+            .nodelay(true)
+            .connect(&addr)
+            .expect("Failed to build connected socket");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(listener.is_listening());
+        assert!(client.is_connected());
+    }
+
+    #[test]
+    fn test_builder_sets_nonblocking_after_bind() {
+        let listener = SocketBuilder::new()
+            .nonblocking(true)
+            .listen(("127.0.0.1", 0), 1)
+            .expect("Failed to build listening socket");
+
+        assert!(matches!(listener.accept(), Err(SocketError::WouldBlock)));
+    }
+}
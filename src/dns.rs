@@ -0,0 +1,223 @@
+use crate::{
+    endpoint::Endpoint,
+    error::{Result, SocketError},
+    socket::ipv4_sockaddr,
+};
+use libc::{
+    addrinfo, c_char, c_int, sockaddr, sockaddr_in, sockaddr_in6, socklen_t, AF_INET, AF_INET6,
+    AF_UNSPEC, EAI_AGAIN, EAI_NONAME, NI_NUMERICHOST, SOCK_STREAM,
+};
+use std::{
+    ffi::CStr,
+    ffi::CString,
+    mem,
+    net::{Ipv4Addr, Ipv6Addr},
+    ptr,
+};
+
+unsafe extern "C" {
+    // resolves a host name into a linked list of socket addresses
+    // node: host name or numeric address to resolve
+    // service: port or service name, NULL when the caller fills in the port itself
+    // hints: restricts the returned addresses (family, socket type, ...)
+    // res: set to the head of the result list, which must be released with freeaddrinfo
+    // returns 0 on success or an EAI_* error code (not errno)
+    fn getaddrinfo(
+        node: *const c_char,
+        service: *const c_char,
+        hints: *const addrinfo,
+        res: *mut *mut addrinfo,
+    ) -> c_int;
+
+    // releases a result list returned by getaddrinfo
+    fn freeaddrinfo(res: *mut addrinfo);
+
+    // turns a socket address back into a host and service name, the reverse of getaddrinfo
+    // addr, addrlen: socket address to look up
+    // host, hostlen: buffer for the host name, numeric unless a PTR record exists
+    // serv, servlen: buffer for the service name, numeric unless the port is well known
+    // flags: NI_NUMERICHOST/NI_NUMERICSERV skip the lookups, NI_NAMEREQD fails without a name
+    // returns 0 on success or an EAI_* error code (not errno)
+    fn getnameinfo(
+        addr: *const sockaddr,
+        addrlen: socklen_t,
+        host: *mut c_char,
+        hostlen: socklen_t,
+        serv: *mut c_char,
+        servlen: socklen_t,
+        flags: c_int,
+    ) -> c_int;
+
+    // describes an EAI_* error code returned by getaddrinfo
+    fn gai_strerror(errcode: c_int) -> *const c_char;
+}
+
+// returns every IPv4 address host resolves to, paired with port
+pub fn resolve(host: &str, port: u16) -> Result<Vec<Endpoint>> {
+    lookup(host, port, AF_INET)
+}
+
+// like resolve but with the IPv6 addresses too, in the order getaddrinfo prefers them
+pub(crate) fn resolve_dual(host: &str, port: u16) -> Result<Vec<Endpoint>> {
+    lookup(host, port, AF_UNSPEC)
+}
+
+fn lookup(host: &str, port: u16, family: c_int) -> Result<Vec<Endpoint>> {
+    let node = CString::new(host)
+        .map_err(|_| SocketError::InvalidInput("Host name contains a null byte"))?;
+
+    let mut hints: addrinfo = unsafe { mem::zeroed() };
+    hints.ai_family = family;
+    // without a socket type every address is returned once per protocol
+    hints.ai_socktype = SOCK_STREAM;
+
+    let mut res: *mut addrinfo = ptr::null_mut();
+    let code = unsafe { getaddrinfo(node.as_ptr(), ptr::null(), &hints, &mut res) };
+
+    if code != 0 {
+        return Err(SocketError::Resolve(code));
+    }
+
+    let mut addrs = Vec::new();
+    let mut entry = res;
+
+    while !entry.is_null() {
+        let info = unsafe { &*entry };
+        if info.ai_family == AF_INET && !info.ai_addr.is_null() {
+            let addr = unsafe { &*(info.ai_addr as *const sockaddr_in) };
+            addrs.push(Endpoint::V4(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                port,
+            ));
+        } else if info.ai_family == AF_INET6 && !info.ai_addr.is_null() {
+            let addr = unsafe { &*(info.ai_addr as *const sockaddr_in6) };
+            addrs.push(Endpoint::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr), port));
+        }
+        entry = info.ai_next;
+    }
+
+    unsafe { freeaddrinfo(res) };
+
+    Ok(addrs)
+}
+
+// NI_MAXHOST and NI_MAXSERV from netdb.h, the libc crate doesn't export them everywhere
+const HOST_BUF_LEN: usize = 1025;
+const SERV_BUF_LEN: usize = 32;
+
+// looks up the host and service name for ip and port, e.g. for logging accepted peers,
+// addresses without a PTR record come back in numeric form
+pub fn reverse_lookup(ip: Ipv4Addr, port: u16) -> Result<(String, String)> {
+    reverse_lookup_addr(&ipv4_sockaddr(ip, port))
+}
+
+fn reverse_lookup_addr(addr: &sockaddr_in) -> Result<(String, String)> {
+    match name_info(addr, 0) {
+        // no name for the address, or the name server could not be reached, the numeric
+        // form never needs one, anything else is a real failure
+        Err(SocketError::Resolve(EAI_AGAIN | EAI_NONAME)) => name_info(addr, NI_NUMERICHOST),
+        res => res,
+    }
+}
+
+fn name_info(addr: &sockaddr_in, flags: c_int) -> Result<(String, String)> {
+    let mut host = [0 as c_char; HOST_BUF_LEN];
+    let mut serv = [0 as c_char; SERV_BUF_LEN];
+
+    let code = unsafe {
+        getnameinfo(
+            addr as *const sockaddr_in as *const sockaddr,
+            mem::size_of::<sockaddr_in>() as socklen_t,
+            host.as_mut_ptr(),
+            host.len() as socklen_t,
+            serv.as_mut_ptr(),
+            serv.len() as socklen_t,
+            flags,
+        )
+    };
+
+    if code != 0 {
+        return Err(SocketError::Resolve(code));
+    }
+
+    let to_string = |buf: &[c_char]| {
+        unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok((to_string(&host), to_string(&serv)))
+}
+
+pub(crate) fn resolve_error_message(code: i32) -> String {
+    unsafe { CStr::from_ptr(gai_strerror(code)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_localhost() {
+        let addrs = resolve("localhost", 80).expect("Failed to resolve localhost");
+
+        assert!(
+            addrs.contains(&Endpoint::V4(Ipv4Addr::LOCALHOST, 80)),
+            "localhost resolved to {:?}",
+            addrs
+        );
+        // resolve only asks for IPv4
+        assert!(addrs.iter().all(|addr| matches!(addr, Endpoint::V4(..))));
+    }
+
+    #[test]
+    fn test_resolve_unknown_host_fails() {
+        let res = resolve("does-not-exist.invalid", 80);
+
+        assert!(matches!(res, Err(SocketError::Resolve(_))));
+        let err = res.unwrap_err();
+        assert!(
+            err.to_string().starts_with("Failed to resolve host: "),
+            "Unexpected message: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_reverse_lookup_loopback() {
+        let (host, service) =
+            reverse_lookup(Ipv4Addr::LOCALHOST, 80).expect("Failed to look up 127.0.0.1");
+
+        assert!(
+            host == "localhost" || host == "127.0.0.1",
+            "127.0.0.1 reversed to {}",
+            host
+        );
+        assert!(
+            service == "http" || service == "80",
+            "port 80 reversed to {}",
+            service
+        );
+    }
+
+    #[test]
+    fn test_reverse_lookup_propagates_real_failures() {
+        let mut addr = ipv4_sockaddr(Ipv4Addr::LOCALHOST, 80);
+        // no family getnameinfo knows
+        addr.sin_family = 255;
+
+        assert_eq!(
+            reverse_lookup_addr(&addr),
+            Err(SocketError::Resolve(libc::EAI_FAMILY))
+        );
+    }
+
+    #[test]
+    fn test_resolve_rejects_null_byte() {
+        assert_eq!(
+            resolve("local\0host", 80),
+            Err(SocketError::InvalidInput("Host name contains a null byte"))
+        );
+    }
+}
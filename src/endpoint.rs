@@ -0,0 +1,247 @@
+use crate::error::{Result, SocketError};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    path::PathBuf,
+};
+
+// the address of one end of a socket, for any of the supported families
+// an unnamed Unix socket (e.g. a client that never called bind) has an empty path, a Linux
+// abstract name keeps its leading null byte
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    V4(Ipv4Addr, u16),
+    V6(Ipv6Addr, u16),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    // None for Unix sockets, which have no IP address
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Endpoint::V4(ip, _) => Some(IpAddr::V4(*ip)),
+            Endpoint::V6(ip, _) => Some(IpAddr::V6(*ip)),
+            Endpoint::Unix(_) => None,
+        }
+    }
+
+    // None for Unix sockets, which have no port
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Endpoint::V4(_, port) | Endpoint::V6(_, port) => Some(*port),
+            Endpoint::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for Endpoint {
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(addr) => addr.into(),
+            SocketAddr::V6(addr) => addr.into(),
+        }
+    }
+}
+
+impl From<SocketAddrV4> for Endpoint {
+    fn from(addr: SocketAddrV4) -> Self {
+        Endpoint::V4(*addr.ip(), addr.port())
+    }
+}
+
+// the flow label and scope id are dropped, so link-local addresses lose their interface
+impl From<SocketAddrV6> for Endpoint {
+    fn from(addr: SocketAddrV6) -> Self {
+        Endpoint::V6(*addr.ip(), addr.port())
+    }
+}
+
+impl From<(IpAddr, u16)> for Endpoint {
+    fn from((ip, port): (IpAddr, u16)) -> Self {
+        SocketAddr::new(ip, port).into()
+    }
+}
+
+impl From<(Ipv4Addr, u16)> for Endpoint {
+    fn from((ip, port): (Ipv4Addr, u16)) -> Self {
+        Endpoint::V4(ip, port)
+    }
+}
+
+impl From<(Ipv6Addr, u16)> for Endpoint {
+    fn from((ip, port): (Ipv6Addr, u16)) -> Self {
+        Endpoint::V6(ip, port)
+    }
+}
+
+// anything an address-taking method like bind, connect or send_to accepts, in the spirit of
+// std's ToSocketAddrs but without name resolution, use connect_host for host names
+pub trait ToEndpoint {
+    fn to_endpoint(&self) -> Result<Endpoint>;
+}
+
+impl ToEndpoint for Endpoint {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(self.clone())
+    }
+}
+
+// "127.0.0.1:80" or "[::1]:80"
+impl ToEndpoint for str {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        self.parse::<SocketAddr>()
+            .map(Endpoint::from)
+            .map_err(|_| SocketError::InvalidInput("Invalid socket address"))
+    }
+}
+
+impl ToEndpoint for String {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        self.as_str().to_endpoint()
+    }
+}
+
+// an IP literal and a port, e.g. ("127.0.0.1", 80)
+impl ToEndpoint for (&str, u16) {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        let ip: IpAddr = self
+            .0
+            .parse()
+            .map_err(|_| SocketError::InvalidInput("Invalid IP address"))?;
+        Ok(Endpoint::from((ip, self.1)))
+    }
+}
+
+impl ToEndpoint for SocketAddr {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl ToEndpoint for SocketAddrV4 {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl ToEndpoint for SocketAddrV6 {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl ToEndpoint for (IpAddr, u16) {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl ToEndpoint for (Ipv4Addr, u16) {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl ToEndpoint for (Ipv6Addr, u16) {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint::from(*self))
+    }
+}
+
+impl<T: ToEndpoint + ?Sized> ToEndpoint for &T {
+    fn to_endpoint(&self) -> Result<Endpoint> {
+        (**self).to_endpoint()
+    }
+}
+
+// formats like std's SocketAddr, "127.0.0.1:80" and "[::1]:80", Unix sockets show their path
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::V4(ip, port) => write!(f, "{}:{}", ip, port),
+            Endpoint::V6(ip, port) => write!(f, "[{}]:{}", ip, port),
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_display_matches_std() {
+        assert_eq!(
+            Endpoint::V4(Ipv4Addr::LOCALHOST, 80).to_string(),
+            "127.0.0.1:80"
+        );
+        assert_eq!(
+            Endpoint::V6(Ipv6Addr::LOCALHOST, 80).to_string(),
+            "[::1]:80"
+        );
+        assert_eq!(
+            Endpoint::Unix(PathBuf::from("/tmp/app.sock")).to_string(),
+            "/tmp/app.sock"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_accessors() {
+        let v4 = Endpoint::V4(Ipv4Addr::LOCALHOST, 8080);
+        assert_eq!(v4.ip(), Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert_eq!(v4.port(), Some(8080));
+
+        let unix = Endpoint::Unix(PathBuf::from("/tmp/app.sock"));
+        assert_eq!(unix.ip(), None);
+        assert_eq!(unix.port(), None);
+    }
+
+    #[test]
+    fn test_endpoint_from_std_addresses() {
+        let v4 = Endpoint::V4(Ipv4Addr::LOCALHOST, 80);
+        assert_eq!(Endpoint::from(SocketAddr::from(([127, 0, 0, 1], 80))), v4);
+        assert_eq!(
+            Endpoint::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 80)),
+            v4
+        );
+        assert_eq!(Endpoint::from((Ipv4Addr::LOCALHOST, 80)), v4);
+
+        let v6 = Endpoint::V6(Ipv6Addr::LOCALHOST, 80);
+        assert_eq!(
+            Endpoint::from(SocketAddr::from((Ipv6Addr::LOCALHOST, 80))),
+            v6
+        );
+        assert_eq!(Endpoint::from((IpAddr::V6(Ipv6Addr::LOCALHOST), 80)), v6);
+    }
+
+    #[test]
+    fn test_to_endpoint_impls() {
+        let v4 = Endpoint::V4(Ipv4Addr::LOCALHOST, 80);
+        assert_eq!("127.0.0.1:80".to_endpoint(), Ok(v4.clone()));
+        assert_eq!(String::from("127.0.0.1:80").to_endpoint(), Ok(v4.clone()));
+        assert_eq!(("127.0.0.1", 80).to_endpoint(), Ok(v4.clone()));
+        assert_eq!(
+            SocketAddr::from(([127, 0, 0, 1], 80)).to_endpoint(),
+            Ok(v4.clone())
+        );
+        assert_eq!((Ipv4Addr::LOCALHOST, 80).to_endpoint(), Ok(v4.clone()));
+        assert_eq!(v4.to_endpoint(), Ok(v4.clone()));
+
+        let v6 = Endpoint::V6(Ipv6Addr::LOCALHOST, 80);
+        assert_eq!("[::1]:80".to_endpoint(), Ok(v6.clone()));
+        assert_eq!(("::1", 80).to_endpoint(), Ok(v6.clone()));
+        assert_eq!((Ipv6Addr::LOCALHOST, 80).to_endpoint(), Ok(v6));
+    }
+
+    #[test]
+    fn test_to_endpoint_rejects_invalid_addresses() {
+        assert_eq!(
+            "127.0.0.1".to_endpoint(),
+            Err(SocketError::InvalidInput("Invalid socket address"))
+        );
+        assert_eq!(
+            ("localhost", 80).to_endpoint(),
+            Err(SocketError::InvalidInput("Invalid IP address"))
+        );
+    }
+}
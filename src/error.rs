@@ -0,0 +1,138 @@
+use crate::dns::resolve_error_message;
+use libc::{EACCES, EADDRINUSE, ECONNREFUSED, EINPROGRESS, EPERM, ETIMEDOUT};
+use std::{fmt, io};
+
+pub type Result<T> = std::result::Result<T, SocketError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketError {
+    // a non-blocking call would have to wait, retry once the socket is ready
+    WouldBlock,
+    // a non-blocking connect was started, wait for the socket to become writable and then
+    // call take_connect_error to learn whether it succeeded
+    InProgress,
+    // a read/write timeout fired or the connection attempt took too long
+    TimedOut,
+    ConnectionRefused,
+    AddrInUse,
+    // the call needs privileges the process lacks, e.g. CAP_NET_RAW or a port below 1024
+    PermissionDenied,
+    // the socket is in the wrong state for the call, e.g. send before connect
+    InvalidState(&'static str),
+    // an argument was rejected before reaching the kernel, e.g. a malformed IP address
+    InvalidInput(&'static str),
+    // the peer closed the connection before all expected data arrived
+    UnexpectedEof,
+    // a ShutdownHandle stopped the listener, see Socket::shutdown_handle
+    Shutdown,
+    Unsupported(&'static str),
+    // getaddrinfo failed, carrying its EAI_* code
+    Resolve(i32),
+    // any other failure, carrying the raw errno
+    Os(i32),
+}
+
+impl SocketError {
+    // categorizes an errno value, the common codes get their own variant
+    pub(crate) fn from_errno(code: i32) -> Self {
+        match code {
+            EADDRINUSE => SocketError::AddrInUse,
+            ECONNREFUSED => SocketError::ConnectionRefused,
+            ETIMEDOUT => SocketError::TimedOut,
+            EINPROGRESS => SocketError::InProgress,
+            EPERM | EACCES => SocketError::PermissionDenied,
+            // EAGAIN and EWOULDBLOCK have the same value on Linux but not everywhere
+            code if io::Error::from_raw_os_error(code).kind() == io::ErrorKind::WouldBlock => {
+                SocketError::WouldBlock
+            }
+            code => SocketError::Os(code),
+        }
+    }
+
+    // must be called right after the failing syscall, before anything else can touch errno
+    pub(crate) fn last_os_error() -> Self {
+        match io::Error::last_os_error().raw_os_error() {
+            Some(code) => Self::from_errno(code),
+            None => SocketError::Os(0),
+        }
+    }
+}
+
+impl fmt::Display for SocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketError::WouldBlock => write!(f, "Operation would block"),
+            SocketError::TimedOut => write!(f, "Operation timed out"),
+            SocketError::InProgress => write!(f, "Operation now in progress"),
+            // include the OS description so messages read like "Address already in use (os error 98)"
+            SocketError::ConnectionRefused => os_error(f, ECONNREFUSED),
+            SocketError::AddrInUse => os_error(f, EADDRINUSE),
+            SocketError::PermissionDenied => write!(f, "Permission denied"),
+            SocketError::InvalidState(msg) => write!(f, "Invalid socket state: {}", msg),
+            SocketError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            SocketError::UnexpectedEof => write!(f, "Unexpected EOF: peer closed the connection"),
+            SocketError::Shutdown => write!(f, "Listener was shut down"),
+            SocketError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            SocketError::Resolve(code) => {
+                write!(
+                    f,
+                    "Failed to resolve host: {}",
+                    resolve_error_message(*code)
+                )
+            }
+            SocketError::Os(code) => os_error(f, *code),
+        }
+    }
+}
+
+fn os_error(f: &mut fmt::Formatter<'_>, code: i32) -> fmt::Result {
+    write!(f, "{}", io::Error::from_raw_os_error(code))
+}
+
+impl std::error::Error for SocketError {}
+
+// lets ? carry a SocketError out of functions returning io::Result, the variants backed by a
+// single errno keep it as the raw OS error, the rest wrap the SocketError itself
+impl From<SocketError> for io::Error {
+    fn from(err: SocketError) -> Self {
+        let kind = match err {
+            SocketError::InProgress => return io::Error::from_raw_os_error(EINPROGRESS),
+            SocketError::ConnectionRefused => return io::Error::from_raw_os_error(ECONNREFUSED),
+            SocketError::AddrInUse => return io::Error::from_raw_os_error(EADDRINUSE),
+            SocketError::Os(code) => return io::Error::from_raw_os_error(code),
+            SocketError::WouldBlock => io::ErrorKind::WouldBlock,
+            SocketError::TimedOut => io::ErrorKind::TimedOut,
+            SocketError::PermissionDenied => io::ErrorKind::PermissionDenied,
+            SocketError::InvalidInput(_) => io::ErrorKind::InvalidInput,
+            SocketError::UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            SocketError::Shutdown => io::ErrorKind::ConnectionAborted,
+            SocketError::Unsupported(_) => io::ErrorKind::Unsupported,
+            SocketError::InvalidState(_) | SocketError::Resolve(_) => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_error_converts_to_io_error() {
+        let err = io::Error::from(SocketError::AddrInUse);
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(err.raw_os_error(), Some(EADDRINUSE));
+
+        let err = io::Error::from(SocketError::Shutdown);
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        assert_eq!(err.to_string(), "Listener was shut down");
+
+        let err = io::Error::from(SocketError::InvalidInput("Invalid IP address"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.get_ref()
+                .and_then(|inner| inner.downcast_ref::<SocketError>()),
+            Some(&SocketError::InvalidInput("Invalid IP address"))
+        );
+    }
+}
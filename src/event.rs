@@ -0,0 +1,513 @@
+#[cfg(target_os = "linux")]
+use crate::socket::poll_timeout_ms;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::{
+    error::{Result, SocketError},
+    socket::Socket,
+};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use libc::c_int;
+#[cfg(target_os = "macos")]
+use libc::{
+    c_void, kevent, timespec, EVFILT_READ, EVFILT_WRITE, EV_ADD, EV_DELETE, EV_DISABLE, EV_ENABLE,
+    EV_EOF, EV_ERROR,
+};
+#[cfg(target_os = "linux")]
+use libc::{
+    epoll_event, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLL_CLOEXEC, EPOLL_CTL_ADD,
+    EPOLL_CTL_DEL, EPOLL_CTL_MOD,
+};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::{
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    time::{Duration, Instant},
+};
+#[cfg(target_os = "macos")]
+use std::{mem, ptr};
+
+#[cfg(target_os = "linux")]
+unsafe extern "C" {
+    // creates a new epoll instance and returns a descriptor referring to it
+    // flags: 0 or EPOLL_CLOEXEC
+    fn epoll_create1(flags: c_int) -> c_int;
+
+    // adds, changes or removes a descriptor in the interest list of an epoll instance
+    // epfd: epoll descriptor returned by epoll_create1
+    // op: EPOLL_CTL_ADD, EPOLL_CTL_MOD or EPOLL_CTL_DEL
+    // fd: descriptor being watched
+    // event: events to watch for plus user data handed back by epoll_wait, ignored for DEL
+    fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut epoll_event) -> c_int;
+
+    // waits for events on the descriptors registered with an epoll instance
+    // epfd: epoll descriptor returned by epoll_create1
+    // events: buffer the ready events are written to
+    // maxevents: capacity of events, must be greater than zero
+    // timeout: milliseconds to wait, -1 waits forever and 0 returns immediately
+    // returns the number of events written, 0 on timeout or -1 on error
+    fn epoll_wait(epfd: c_int, events: *mut epoll_event, maxevents: c_int, timeout: c_int)
+        -> c_int;
+}
+
+#[cfg(target_os = "macos")]
+unsafe extern "C" {
+    // creates a new kernel event queue and returns a descriptor referring to it
+    fn kqueue() -> c_int;
+
+    // applies a list of changes to a kqueue and then waits for pending events
+    // kq: queue descriptor returned by kqueue
+    // changelist: filters to add, modify or delete, NULL when only waiting
+    // nchanges: number of entries in changelist
+    // eventlist: buffer the triggered events are written to, NULL when only registering
+    // nevents: capacity of eventlist
+    // timeout: how long to wait, NULL waits forever
+    // returns the number of events written, 0 on timeout or -1 on error
+    fn kevent(
+        kq: c_int,
+        changelist: *const kevent,
+        nchanges: c_int,
+        eventlist: *mut kevent,
+        nevents: c_int,
+        timeout: *const timespec,
+    ) -> c_int;
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+unsafe extern "C" {
+    // closes the epoll or kqueue descriptor
+    // fd: raw file descriptor
+    fn close(fd: c_int) -> c_int;
+}
+
+// upper bound on the events returned by a single wait, the rest are picked up by the next one
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const MAX_EVENTS: usize = 256;
+
+// what a registered socket should be watched for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interest {
+    Readable,
+    Writable,
+    Both,
+}
+
+impl Interest {
+    pub fn is_readable(self) -> bool {
+        matches!(self, Interest::Readable | Interest::Both)
+    }
+
+    pub fn is_writable(self) -> bool {
+        matches!(self, Interest::Writable | Interest::Both)
+    }
+}
+
+// a readiness notification for the socket registered under token, errors and hangups are
+// reported as readable so the next read surfaces them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub token: u64,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+// level-triggered readiness notifications for many sockets at once, backed by epoll
+#[cfg(target_os = "linux")]
+pub struct Epoll {
+    fd: RawFd,
+}
+
+#[cfg(target_os = "linux")]
+impl Epoll {
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+
+        if fd == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(Epoll { fd })
+    }
+
+    // starts watching sock, events for it carry token
+    pub fn register(&self, sock: &Socket, token: u64, interest: Interest) -> Result<()> {
+        self.control(EPOLL_CTL_ADD, sock, token, interest)
+    }
+
+    // replaces the token and interest of an already registered socket
+    pub fn reregister(&self, sock: &Socket, token: u64, interest: Interest) -> Result<()> {
+        self.control(EPOLL_CTL_MOD, sock, token, interest)
+    }
+
+    // stops watching sock, closing the socket also removes it
+    pub fn deregister(&self, sock: &Socket) -> Result<()> {
+        // kernels before 2.6.9 insist on a non-null event even though DEL ignores it
+        let mut event = epoll_event { events: 0, u64: 0 };
+        let res = unsafe { epoll_ctl(self.fd, EPOLL_CTL_DEL, sock.as_raw_fd(), &mut event) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn control(&self, op: c_int, sock: &Socket, token: u64, interest: Interest) -> Result<()> {
+        let mut events = 0;
+        if interest.is_readable() {
+            events |= EPOLLIN;
+        }
+        if interest.is_writable() {
+            events |= EPOLLOUT;
+        }
+
+        let mut event = epoll_event {
+            events: events as u32,
+            u64: token,
+        };
+        let res = unsafe { epoll_ctl(self.fd, op, sock.as_raw_fd(), &mut event) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // waits until at least one registered socket is ready, an empty list means the timeout
+    // elapsed, None waits forever
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<Vec<Event>> {
+        let deadline = timeout.map(|dur| Instant::now() + dur);
+        let mut events = [epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+
+        let count = loop {
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    poll_timeout_ms(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => -1,
+            };
+            let res = unsafe {
+                epoll_wait(
+                    self.fd,
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as c_int,
+                    timeout_ms,
+                )
+            };
+
+            if res == -1 {
+                // a signal interrupted the wait, keep waiting for whatever time is left
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(SocketError::last_os_error());
+            }
+
+            break res as usize;
+        };
+
+        Ok(events[..count]
+            .iter()
+            .map(|event| {
+                // epoll_event is packed on x86_64, copy the fields out instead of borrowing them
+                let flags = event.events as c_int;
+                let token = event.u64;
+                Event {
+                    token,
+                    readable: flags & (EPOLLIN | EPOLLHUP | EPOLLERR) != 0,
+                    writable: flags & (EPOLLOUT | EPOLLERR) != 0,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl AsRawFd for Epoll {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe { close(self.fd) };
+    }
+}
+
+// level-triggered readiness notifications for many sockets at once, backed by kqueue
+#[cfg(target_os = "macos")]
+pub struct Kqueue {
+    fd: RawFd,
+}
+
+#[cfg(target_os = "macos")]
+impl Kqueue {
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { kqueue() };
+
+        if fd == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(Kqueue { fd })
+    }
+
+    // starts watching sock, events for it carry token
+    pub fn register(&self, sock: &Socket, token: u64, interest: Interest) -> Result<()> {
+        self.apply(&filters(sock, token, interest))
+    }
+
+    // replaces the token and interest of an already registered socket
+    pub fn reregister(&self, sock: &Socket, token: u64, interest: Interest) -> Result<()> {
+        self.apply(&filters(sock, token, interest))
+    }
+
+    // stops watching sock, closing the socket also removes it
+    pub fn deregister(&self, sock: &Socket) -> Result<()> {
+        self.apply(&[
+            change(sock, EVFILT_READ, EV_DELETE, 0),
+            change(sock, EVFILT_WRITE, EV_DELETE, 0),
+        ])
+    }
+
+    fn apply(&self, changes: &[kevent]) -> Result<()> {
+        // without an event list, a failed change makes kevent itself return -1
+        let res = unsafe {
+            kevent(
+                self.fd,
+                changes.as_ptr(),
+                changes.len() as c_int,
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // waits until at least one registered socket is ready, an empty list means the timeout
+    // elapsed, None waits forever
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<Vec<Event>> {
+        let deadline = timeout.map(|dur| Instant::now() + dur);
+        let mut events: [kevent; MAX_EVENTS] = unsafe { mem::zeroed() };
+
+        let count = loop {
+            let ts = deadline.map(|deadline| {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                timespec {
+                    tv_sec: remaining.as_secs() as _,
+                    tv_nsec: remaining.subsec_nanos() as _,
+                }
+            });
+            let ts_ptr = match ts.as_ref() {
+                Some(ts) => ts as *const timespec,
+                None => ptr::null(),
+            };
+            let res = unsafe {
+                kevent(
+                    self.fd,
+                    ptr::null(),
+                    0,
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as c_int,
+                    ts_ptr,
+                )
+            };
+
+            if res == -1 {
+                // a signal interrupted the wait, keep waiting for whatever time is left
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(SocketError::last_os_error());
+            }
+
+            break res as usize;
+        };
+
+        // each filter fires on its own, so a socket ready both ways shows up twice
+        Ok(events[..count]
+            .iter()
+            .map(|event| {
+                let failed = event.flags & (EV_EOF | EV_ERROR) != 0;
+                Event {
+                    token: event.udata as usize as u64,
+                    readable: event.filter == EVFILT_READ || failed,
+                    writable: event.filter == EVFILT_WRITE,
+                }
+            })
+            .collect())
+    }
+}
+
+// kqueue watches reading and writing with separate filters, both are always added so
+// reregister and deregister never touch a filter that doesn't exist, the unwanted one
+// is just disabled
+#[cfg(target_os = "macos")]
+fn filters(sock: &Socket, token: u64, interest: Interest) -> [kevent; 2] {
+    let toggle = |enable: bool| if enable { EV_ENABLE } else { EV_DISABLE };
+    [
+        change(
+            sock,
+            EVFILT_READ,
+            EV_ADD | toggle(interest.is_readable()),
+            token,
+        ),
+        change(
+            sock,
+            EVFILT_WRITE,
+            EV_ADD | toggle(interest.is_writable()),
+            token,
+        ),
+    ]
+}
+
+#[cfg(target_os = "macos")]
+fn change(sock: &Socket, filter: i16, flags: u16, token: u64) -> kevent {
+    kevent {
+        ident: sock.as_raw_fd() as usize,
+        filter,
+        flags,
+        fflags: 0,
+        data: 0,
+        udata: token as usize as *mut c_void,
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl AsRawFd for Kqueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for Kqueue {
+    fn drop(&mut self) {
+        unsafe { close(self.fd) };
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_epoll_reports_connection_on_listener() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let epoll = Epoll::new().expect("Failed to create epoll instance");
+        epoll
+            .register(&listener, 7, Interest::Readable)
+            .expect("Failed to register listener");
+
+        let events = epoll
+            .wait(Some(Duration::from_millis(10)))
+            .expect("Failed to wait for events");
+        assert!(events.is_empty());
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+
+        let events = epoll
+            .wait(Some(Duration::from_secs(1)))
+            .expect("Failed to wait for events");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].token, 7);
+        assert!(events[0].readable);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_epoll_deregister_stops_events() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+
+        let epoll = Epoll::new().expect("Failed to create epoll instance");
+        epoll
+            .register(&client, 1, Interest::Writable)
+            .expect("Failed to register client");
+        let events = epoll
+            .wait(Some(Duration::from_secs(1)))
+            .expect("Failed to wait for events");
+        assert_eq!(events.len(), 1);
+        assert!(events[0].writable);
+
+        epoll
+            .deregister(&client)
+            .expect("Failed to deregister client");
+        let events = epoll
+            .wait(Some(Duration::from_millis(10)))
+            .expect("Failed to wait for events");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn test_kqueue_reports_connection_on_listener() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let kqueue = Kqueue::new().expect("Failed to create kqueue");
+        kqueue
+            .register(&listener, 7, Interest::Readable)
+            .expect("Failed to register listener");
+
+        let events = kqueue
+            .wait(Some(Duration::from_millis(10)))
+            .expect("Failed to wait for events");
+        assert!(events.is_empty());
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+
+        let events = kqueue
+            .wait(Some(Duration::from_secs(1)))
+            .expect("Failed to wait for events");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].token, 7);
+        assert!(events[0].readable);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::{Result, SocketError};
+use libc::{c_char, c_uint, IF_NAMESIZE};
+use std::ffi::{CStr, CString};
+
+unsafe extern "C" {
+    // ifname: interface name such as "eth0"
+    // returns the interface's index, or 0 when there is no such interface (errno is set)
+    fn if_nametoindex(ifname: *const c_char) -> c_uint;
+
+    // ifindex: interface index, e.g. from if_nametoindex or an IPV6_PKTINFO message
+    // ifname: buffer of at least IF_NAMESIZE bytes for the null terminated name
+    // returns ifname, or NULL when there is no such interface (errno is set)
+    fn if_indextoname(ifindex: c_uint, ifname: *mut c_char) -> *mut c_char;
+}
+
+// turns a name such as "eth0" into the index the kernel uses for multicast membership and
+// IPv6 scope ids
+pub fn interface_index(name: &str) -> Result<u32> {
+    let name = CString::new(name)
+        .map_err(|_| SocketError::InvalidInput("Interface name contains a null byte"))?;
+
+    let index = unsafe { if_nametoindex(name.as_ptr()) };
+
+    if index == 0 {
+        return Err(SocketError::last_os_error());
+    }
+
+    Ok(index)
+}
+
+// the reverse of interface_index
+pub fn interface_name(index: u32) -> Result<String> {
+    let mut name = [0 as c_char; IF_NAMESIZE];
+
+    let res = unsafe { if_indextoname(index, name.as_mut_ptr()) };
+
+    if res.is_null() {
+        return Err(SocketError::last_os_error());
+    }
+
+    Ok(unsafe { CStr::from_ptr(name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    const LOOPBACK: &str = "lo";
+    #[cfg(not(target_os = "linux"))]
+    const LOOPBACK: &str = "lo0";
+
+    #[test]
+    fn test_loopback_round_trips_through_index() {
+        let index = interface_index(LOOPBACK).expect("Failed to look up loopback index");
+        assert!(index > 0);
+        assert_eq!(interface_name(index), Ok(LOOPBACK.to_string()));
+    }
+
+    #[test]
+    fn test_unknown_interface_is_an_error() {
+        assert!(interface_index("no-such-if0").is_err());
+        assert!(interface_index("lo\0").is_err());
+    }
+}
@@ -1 +1,22 @@
+mod builder;
+mod dns;
+mod endpoint;
+mod error;
+mod event;
+mod interface;
 mod socket;
+
+pub use builder::SocketBuilder;
+pub use dns::{resolve, reverse_lookup};
+pub use endpoint::{Endpoint, ToEndpoint};
+pub use error::{Result, SocketError};
+#[cfg(target_os = "linux")]
+pub use event::Epoll;
+#[cfg(target_os = "macos")]
+pub use event::Kqueue;
+pub use event::{Event, Interest};
+pub use interface::{interface_index, interface_name};
+pub use socket::{
+    select_readable, Cork, Incoming, KeepaliveParams, MtuDiscover, PeerCred, ReadHalf, Shutdown,
+    ShutdownHandle, Socket, SocketState, SocketStats, TcpInfo, WriteHalf,
+};
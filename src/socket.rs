@@ -1,5 +1,39 @@
-use libc::{in_addr, sockaddr, sockaddr_in, socklen_t, AF_INET, SOCK_STREAM};
-use std::{mem, net::Ipv4Addr, os::unix::io::RawFd};
+use crate::{
+    dns::{resolve, resolve_dual},
+    endpoint::{Endpoint, ToEndpoint},
+    error::{Result, SocketError},
+};
+use libc::{
+    c_char, c_int, c_short, c_uint, c_ulong, c_void, fd_set, in6_addr, in_addr, iovec, ip_mreq,
+    msghdr, nfds_t, off_t, pollfd, sa_family_t, sockaddr, sockaddr_in, sockaddr_in6,
+    sockaddr_storage, sockaddr_un, socklen_t, timeval, AF_INET, AF_INET6, AF_UNIX, AF_UNSPEC,
+    CMSG_DATA, CMSG_FIRSTHDR, CMSG_LEN, CMSG_NXTHDR, CMSG_SPACE, EAFNOSUPPORT, ECONNABORTED,
+    FD_CLOEXEC, FD_ISSET, FD_SET, FD_SETSIZE, FD_ZERO, FIONREAD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL,
+    F_SETFD, F_SETFL, IPPROTO_IP, IPPROTO_IPV6, IPPROTO_TCP, IPV6_V6ONLY, IP_ADD_MEMBERSHIP,
+    IP_DROP_MEMBERSHIP, IP_MULTICAST_IF, IP_MULTICAST_LOOP, IP_MULTICAST_TTL, IP_TOS, IP_TTL,
+    MSG_CTRUNC, MSG_OOB, MSG_PEEK, MSG_WAITALL, O_NONBLOCK, POLLIN, POLLOUT, SCM_RIGHTS, SHUT_RD,
+    SHUT_RDWR, SHUT_WR, SOCK_DGRAM, SOCK_STREAM, SOL_SOCKET, SO_ACCEPTCONN, SO_BROADCAST, SO_ERROR,
+    SO_KEEPALIVE, SO_LINGER, SO_OOBINLINE, SO_RCVBUF, SO_RCVLOWAT, SO_RCVTIMEO, SO_REUSEADDR,
+    SO_SNDBUF, SO_SNDTIMEO, SO_TYPE, TCP_KEEPCNT, TCP_KEEPINTVL, TCP_NODELAY,
+};
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io, mem,
+    net::{Ipv4Addr, Ipv6Addr, TcpStream},
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+    },
+    path::PathBuf,
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 unsafe extern "C" {
     // domain: Communication domain (AF_INET = IPv4).
@@ -8,6 +42,12 @@ unsafe extern "C" {
     // Returns a valid file descriptor (RawFd) or -1 if there's an error.
     fn socket(domain: i32, type_: i32, protocol: i32) -> i32;
 
+    // creates two sockets connected to each other
+    // domain, type_, protocol: same as socket, only AF_UNIX is widely supported
+    // sv: receives the two descriptors
+    // returns 0 on success or -1 on error
+    fn socketpair(domain: i32, type_: i32, protocol: i32, sv: *mut i32) -> i32;
+
     // sockfd: file descriptor for the socket
     // addr: A pointer to a socket address structure
     // addrlen The size (in bytes) of the socket address structure pointed to by addr
@@ -20,13 +60,40 @@ unsafe extern "C" {
     // sockfd: file descriptor for the socket
     // addr: A pointer to a client socket address structure
     // addrlen The size (in bytes) of the client socket address structure pointed to by addr
+    #[cfg(not(target_os = "linux"))]
     fn accept(sockfd: i32, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32;
 
+    // like accept, but sets flags on the new descriptor atomically
+    // flags: SOCK_NONBLOCK and/or SOCK_CLOEXEC
+    #[cfg(target_os = "linux")]
+    fn accept4(sockfd: i32, addr: *mut sockaddr, addrlen: *mut socklen_t, flags: i32) -> i32;
+
     // connects to a remote TCP socket
     // sockfd: file descriptor for the socket
     // addr: A pointer to a client socket address structure
     // addrlen The size (in bytes) of the client socket address structure pointed to by addr
-    fn connect(sockfd: i32, addr: *const sockaddr, addrlen: *const socklen_t) -> i32;
+    fn connect(sockfd: i32, addr: *const sockaddr, addrlen: socklen_t) -> i32;
+
+    // connects and sends initial data in one call, with CONNECT_DATA_IDEMPOTENT the data may
+    // ride along with the SYN (TCP Fast Open)
+    // socket: file descriptor for the socket
+    // endpoints: source (optional) and destination addresses
+    // associd: SAE_ASSOCID_ANY for TCP
+    // flags: CONNECT_RESUME_ON_READ_WRITE, CONNECT_DATA_IDEMPOTENT, ...
+    // iov, iovcnt: the initial data
+    // len: set to the number of bytes queued from iov
+    // connid: set to the connection id, may be NULL
+    #[cfg(target_os = "macos")]
+    fn connectx(
+        socket: i32,
+        endpoints: *const libc::sa_endpoints_t,
+        associd: libc::sae_associd_t,
+        flags: c_uint,
+        iov: *const iovec,
+        iovcnt: c_uint,
+        len: *mut usize,
+        connid: *mut libc::sae_connid_t,
+    ) -> i32;
 
     // sockfd: file descriptor for the socket
     // buf: a pointer to a buffer that holds the data
@@ -54,12 +121,544 @@ unsafe extern "C" {
     //- -1: Error occurred (check errno).
     fn recv(sockfd: i32, buf: *mut u8, len: usize, flags: i32) -> isize;
 
+    // sends a datagram to a specific address, used on unconnected (UDP) sockets
+    // sockfd: file descriptor for the socket
+    // buf, len, flags: same as send
+    // dest_addr: A pointer to the destination socket address structure
+    // addrlen: The size (in bytes) of the destination socket address structure
+    fn sendto(
+        sockfd: i32,
+        buf: *const u8,
+        len: usize,
+        flags: i32,
+        dest_addr: *const sockaddr,
+        addrlen: socklen_t,
+    ) -> isize;
+
+    // receives a datagram and records who sent it
+    // sockfd: file descriptor for the socket
+    // buf, len, flags: same as recv
+    // src_addr: A pointer to a socket address structure filled with the sender address
+    // addrlen: in: size of src_addr, out: size of the address actually stored
+    fn recvfrom(
+        sockfd: i32,
+        buf: *mut u8,
+        len: usize,
+        flags: i32,
+        src_addr: *mut sockaddr,
+        addrlen: *mut socklen_t,
+    ) -> isize;
+
+    // gathers data from several buffers into one send, unlike writev it takes send flags
+    // sockfd: file descriptor for the socket
+    // msg: msghdr whose msg_iov/msg_iovlen list the buffers, at most IOV_MAX of them
+    // flags: same as send
+    // returns the total number of bytes written or -1 on error
+    fn sendmsg(sockfd: i32, msg: *const msghdr, flags: i32) -> isize;
+
+    // copies data between two descriptors inside the kernel (Linux signature)
+    // out_fd: socket to write to
+    // in_fd: file to read from, must support mmap-like access (a regular file)
+    // offset: where to start reading, advanced past the bytes sent
+    // count: maximum number of bytes to copy
+    // returns the number of bytes sent or -1 on error
+    #[cfg(target_os = "linux")]
+    fn sendfile(out_fd: i32, in_fd: i32, offset: *mut off_t, count: usize) -> isize;
+
+    // changes the calling thread's signal mask
+    // how: SIG_BLOCK adds set to the mask, SIG_SETMASK replaces the mask with set
+    // oldset: filled with the previous mask, may be NULL
+    #[cfg(target_os = "linux")]
+    fn pthread_sigmask(
+        how: c_int,
+        set: *const libc::sigset_t,
+        oldset: *mut libc::sigset_t,
+    ) -> c_int;
+
+    #[cfg(target_os = "linux")]
+    fn sigemptyset(set: *mut libc::sigset_t) -> c_int;
+
+    #[cfg(target_os = "linux")]
+    fn sigaddset(set: *mut libc::sigset_t, signum: c_int) -> c_int;
+
+    // returns 1 if signum is in set, 0 if not
+    #[cfg(target_os = "linux")]
+    fn sigismember(set: *const libc::sigset_t, signum: c_int) -> c_int;
+
+    // fills set with the signals that are blocked and waiting to be delivered
+    #[cfg(target_os = "linux")]
+    fn sigpending(set: *mut libc::sigset_t) -> c_int;
+
+    // removes one pending signal in set, waiting at most timeout, returns its number or -1
+    // info: filled with details about the signal, may be NULL
+    #[cfg(target_os = "linux")]
+    fn sigtimedwait(
+        set: *const libc::sigset_t,
+        info: *mut libc::siginfo_t,
+        timeout: *const libc::timespec,
+    ) -> c_int;
+
+    // sends a file over a stream socket (macOS signature)
+    // fd: file to read from
+    // s: socket to write to
+    // offset: where to start reading
+    // len: in, the number of bytes to send (0 = until EOF), out, the number actually sent
+    // hdtr: optional headers/trailers sent around the file data, NULL for none
+    // flags: reserved, must be 0
+    // returns 0 on success or -1 on error, len is set either way
+    #[cfg(target_os = "macos")]
+    fn sendfile(
+        fd: i32,
+        s: i32,
+        offset: off_t,
+        len: *mut off_t,
+        hdtr: *mut libc::sf_hdtr,
+        flags: c_int,
+    ) -> c_int;
+
+    // receives data plus any ancillary control messages, the counterpart of sendmsg
+    // sockfd: file descriptor for the socket
+    // msg: msghdr whose buffers are filled in, msg_controllen is updated to what arrived
+    // flags: same as recv, plus MSG_CMSG_CLOEXEC on Linux for received descriptors
+    // returns the number of bytes read, 0 when the peer closed the connection, or -1
+    fn recvmsg(sockfd: i32, msg: *mut msghdr, flags: i32) -> isize;
+
+    // receives several datagrams at once, each into its own mmsghdr (Linux)
+    // sockfd: file descriptor for the socket
+    // msgvec: array of mmsghdr, msg_len of each is set to the size of its datagram
+    // vlen: number of entries in msgvec
+    // flags: same as recv, plus MSG_WAITFORONE to only block for the first datagram
+    // timeout: overall limit, NULL to wait according to flags and the socket timeout
+    // returns the number of datagrams received or -1 on error
+    #[cfg(target_os = "linux")]
+    fn recvmmsg(
+        sockfd: i32,
+        msgvec: *mut libc::mmsghdr,
+        vlen: c_uint,
+        flags: c_int,
+        timeout: *mut libc::timespec,
+    ) -> c_int;
+
+    // sends several datagrams at once, each described by its own mmsghdr (Linux)
+    // sockfd, msgvec, vlen: same as recvmmsg, msg_len is set to the bytes sent
+    // flags: same as send
+    // returns the number of datagrams sent, which can be less than vlen, or -1 on error
+    #[cfg(target_os = "linux")]
+    fn sendmmsg(sockfd: i32, msgvec: *mut libc::mmsghdr, vlen: c_uint, flags: c_int) -> c_int;
+
+    // scatters one read across several buffers, filling each in order
+    // fd: file descriptor to read from
+    // iov: array of iovec structs, each pointing at a buffer and its length
+    // iovcnt: number of entries in iov, at most IOV_MAX
+    // returns the total number of bytes read, 0 when the peer closed the connection, or -1
+    fn readv(fd: i32, iov: *const iovec, iovcnt: c_int) -> isize;
+
+    // shuts down part of a full-duplex connection
+    // sockfd: file descriptor for the socket
+    // how: SHUT_RD (no more receives), SHUT_WR (no more sends) or SHUT_RDWR (both)
+    fn shutdown(sockfd: i32, how: i32) -> i32;
+
+    // retrieves the local address a socket is bound to
+    // sockfd: file descriptor for the socket
+    // addr: A pointer to a socket address structure the address is written to
+    // addrlen: in: size of addr, out: size of the address actually stored
+    fn getsockname(sockfd: i32, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32;
+
+    // retrieves the address of the peer a socket is connected to
+    // sockfd, addr, addrlen: same as getsockname
+    fn getpeername(sockfd: i32, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32;
+
+    // sets a socket option
+    // sockfd: file descriptor for the socket
+    // level: protocol level the option belongs to (SOL_SOCKET, IPPROTO_TCP, ...)
+    // optname: the option to set (SO_REUSEADDR, ...)
+    // optval: A pointer to the new value, usually a C int
+    // optlen: The size (in bytes) of the value pointed to by optval
+    fn setsockopt(
+        sockfd: i32,
+        level: i32,
+        optname: i32,
+        optval: *const c_void,
+        optlen: socklen_t,
+    ) -> i32;
+
+    // reads a socket option
+    // sockfd, level, optname: same as setsockopt
+    // optval: A pointer to a buffer the current value is written to
+    // optlen: in: size of the buffer, out: size of the value actually stored
+    fn getsockopt(
+        sockfd: i32,
+        level: i32,
+        optname: i32,
+        optval: *mut c_void,
+        optlen: *mut socklen_t,
+    ) -> i32;
+
+    // waits for one of a set of file descriptors to become ready
+    // fds: array of pollfd structs, each naming a descriptor and the events to wait for
+    // nfds: number of entries in fds
+    // timeout: milliseconds to wait, -1 waits forever and 0 returns immediately
+    // returns the number of ready descriptors, 0 on timeout or -1 on error
+    fn poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int;
+
+    // reads or changes the properties of a file descriptor
+    // fd: raw file descriptor
+    // cmd: F_GETFL returns the status flags, F_SETFL replaces them with the third argument,
+    // F_GETFD/F_SETFD do the same for the descriptor flags (FD_CLOEXEC)
+    fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+
+    // makes newfd a copy of oldfd, closing whatever newfd referred to first, the copy does
+    // not inherit FD_CLOEXEC
+    fn dup2(oldfd: i32, newfd: i32) -> i32;
+
+    // waits for any of several file descriptors to become ready, the sets are updated in place
+    // nfds: highest descriptor in any of the sets plus one
+    // readfds/writefds/errorfds: descriptors to watch, NULL to skip a set
+    // timeout: how long to wait, NULL waits forever
+    // returns the number of ready descriptors, 0 on timeout or -1 on error
+    fn select(
+        nfds: c_int,
+        readfds: *mut fd_set,
+        writefds: *mut fd_set,
+        errorfds: *mut fd_set,
+        timeout: *mut timeval,
+    ) -> c_int;
+
+    // reads the effective user and group of the peer of a Unix domain socket (macOS)
+    // socket: connected Unix domain socket
+    // euid, egid: receive the peer's ids
+    #[cfg(target_os = "macos")]
+    fn getpeereid(socket: i32, euid: *mut libc::uid_t, egid: *mut libc::gid_t) -> i32;
+
+    // device-specific control call, used for queries without a socket option
+    // fd: raw file descriptor
+    // request: FIONREAD stores the number of readable bytes in the c_int the third argument
+    // points to
+    fn ioctl(fd: i32, request: c_ulong, ...) -> i32;
+
+    // creates a unidirectional pipe, used to wake a serve loop from another thread
+    // fds: filled with the read end, then the write end
+    fn pipe(fds: *mut i32) -> i32;
+
+    // writes count bytes from buf to a file descriptor such as a pipe
+    fn write(fd: i32, buf: *const c_void, count: usize) -> isize;
+
     // closes the socket
     // fd: raw file descriptor
     fn close(fd: i32) -> i32;
 }
 
-#[derive(Debug, PartialEq)]
+// limit on the number of buffers per sendmsg/readv call, 1024 on both Linux and macOS
+const IOV_MAX: usize = 1024;
+
+// writing to a connection the peer has closed raises SIGPIPE, which kills the process unless
+// handled, ask for a plain EPIPE error instead, macOS has no MSG_NOSIGNAL and sets
+// SO_NOSIGPIPE on each socket instead
+#[cfg(target_os = "linux")]
+const SEND_FLAGS: c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(target_os = "linux"))]
+const SEND_FLAGS: c_int = 0;
+
+// Linux calls it TCP_CORK, the BSDs TCP_NOPUSH, both hold back partial segments while set
+#[cfg(target_os = "linux")]
+const CORK_OPTION: c_int = libc::TCP_CORK;
+#[cfg(target_os = "macos")]
+const CORK_OPTION: c_int = libc::TCP_NOPUSH;
+
+// the idle time before the first keepalive probe, macOS names it TCP_KEEPALIVE
+#[cfg(target_os = "linux")]
+const KEEPIDLE_OPTION: c_int = libc::TCP_KEEPIDLE;
+#[cfg(target_os = "macos")]
+const KEEPIDLE_OPTION: c_int = libc::TCP_KEEPALIVE;
+
+// most datagrams handled by one recv_mmsg/send_mmsg call, UIO_MAXIOV caps it at 1024 anyway
+const MMSG_BATCH: usize = 64;
+
+// control buffer for a single SCM_RIGHTS descriptor, counted in u64s so it is aligned for
+// cmsghdr, CMSG_SPACE(sizeof(int)) is 24 bytes on 64-bit Linux and 16 on macOS
+const FD_CONTROL_WORDS: usize = 4;
+
+// control buffer for the IP_PKTINFO message recv_from_with_info asks for, in_pktinfo is
+// 12 bytes, CMSG_SPACE of it is 32 on 64-bit Linux
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const PKTINFO_CONTROL_WORDS: usize = 8;
+
+// how long connect_happy_eyeballs gives one address before also trying the next, the value
+// RFC 8305 recommends
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// room for a congestion control algorithm name including its null byte, the kernel's
+// TCP_CA_NAME_MAX, which libc does not export
+#[cfg(target_os = "linux")]
+const TCP_CA_NAME_MAX: usize = 16;
+
+// largest frame recv_framed accepts unless set_max_frame_len says otherwise
+const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// builds an IPv4 socket address
+pub(crate) fn ipv4_sockaddr(ip: Ipv4Addr, port: u16) -> sockaddr_in {
+    // zeroed so the padding (sin_zero) doesn't have to be named, its type differs per platform
+    let mut addr: sockaddr_in = unsafe { mem::zeroed() };
+    // length of the socket address struct itself, only the BSDs have the field
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    {
+        addr.sin_len = mem::size_of::<sockaddr_in>() as u8;
+    }
+    addr.sin_family = AF_INET as sa_family_t; // u8 on macOS, u16 on Linux
+    addr.sin_port = port.to_be(); // port in big-endian notation
+    addr.sin_addr = in_addr {
+        s_addr: u32::from(ip).to_be(),
+    };
+    addr
+}
+
+// reads the IP and host-order port back out of an IPv4 socket address
+fn ipv4_from_sockaddr(addr: &sockaddr_in) -> (Ipv4Addr, u16) {
+    (
+        Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+        u16::from_be(addr.sin_port),
+    )
+}
+
+// a zero timeval disables the timeout, so a zero duration would silently mean "forever"
+fn duration_to_timeval(dur: Option<Duration>) -> Result<timeval> {
+    match dur {
+        None => Ok(timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        }),
+        Some(dur) if dur.is_zero() => Err(SocketError::InvalidInput(
+            "Timeout must be greater than zero",
+        )),
+        Some(dur) => {
+            let mut tv = timeval {
+                tv_sec: dur.as_secs() as _,
+                tv_usec: dur.subsec_micros() as _,
+            };
+            // round sub-microsecond timeouts up so they don't turn into zero
+            if tv.tv_sec == 0 && tv.tv_usec == 0 {
+                tv.tv_usec = 1;
+            }
+            Ok(tv)
+        }
+    }
+}
+
+// poll takes whole milliseconds, round up so short timeouts don't become a busy poll
+pub(crate) fn poll_timeout_ms(dur: Duration) -> c_int {
+    let millis = dur.as_nanos().div_ceil(1_000_000);
+    millis.min(c_int::MAX as u128) as c_int
+}
+
+// the IP header only has a single byte for the TTL
+fn ttl_to_int(ttl: u32) -> Result<c_int> {
+    if ttl > 255 {
+        return Err(SocketError::InvalidInput("TTL must be at most 255"));
+    }
+    Ok(ttl as c_int)
+}
+
+// keepalive times are whole seconds and the kernel rejects 0
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn keepalive_secs(dur: Duration) -> Result<c_int> {
+    let secs = dur.as_secs() + (dur.subsec_nanos() > 0) as u64;
+    c_int::try_from(secs)
+        .ok()
+        .filter(|&secs| secs > 0)
+        .ok_or(SocketError::InvalidInput(
+            "Keepalive times must be between 1 second and i32::MAX seconds",
+        ))
+}
+
+// l_linger is whole seconds, a sub-second linger rounds up to 1 so it doesn't turn into the
+// reset-on-close that 0 means
+fn linger_secs(dur: Duration) -> Result<c_int> {
+    let secs = dur.as_secs() + (dur.subsec_nanos() > 0) as u64;
+    c_int::try_from(secs).map_err(|_| SocketError::InvalidInput("Linger time is too large"))
+}
+
+fn buffer_size_to_int(size: usize) -> Result<c_int> {
+    c_int::try_from(size).map_err(|_| SocketError::InvalidInput("Buffer size is too large"))
+}
+
+// builds an IPv6 socket address
+fn ipv6_sockaddr(ip: Ipv6Addr, port: u16) -> sockaddr_in6 {
+    let mut addr: sockaddr_in6 = unsafe { mem::zeroed() };
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    {
+        addr.sin6_len = mem::size_of::<sockaddr_in6>() as u8;
+    }
+    addr.sin6_family = AF_INET6 as sa_family_t;
+    addr.sin6_port = port.to_be(); // port in big-endian notation
+    addr.sin6_addr = in6_addr {
+        s6_addr: ip.octets(), // already in network byte order
+    };
+    addr
+}
+
+// builds a Unix domain socket address from a filesystem path
+fn unix_sockaddr(path: &str) -> Result<sockaddr_un> {
+    let mut addr: sockaddr_un = unsafe { mem::zeroed() };
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    {
+        addr.sun_len = mem::size_of::<sockaddr_un>() as u8;
+    }
+    addr.sun_family = AF_UNIX as sa_family_t;
+
+    // leave room for the terminating null byte, anything longer would be cut off by the
+    // kernel or read past the end of sun_path
+    if path.len() >= addr.sun_path.len() {
+        return Err(SocketError::InvalidInput(
+            "Unix socket path is too long, sun_path holds at most 103 bytes on macOS and 107 on Linux",
+        ));
+    }
+    // an embedded null would silently shorten the path to whatever comes before it
+    if path.as_bytes().contains(&0) {
+        return Err(SocketError::InvalidInput(
+            "Unix socket path contains a null byte",
+        ));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(path.bytes()) {
+        *dst = src as c_char;
+    }
+    addr.sun_path[path.len()] = 0;
+
+    Ok(addr)
+}
+
+// builds a Linux abstract socket address, the name lives in a kernel namespace instead of
+// the filesystem and disappears with the last socket bound to it, it is marked by a leading
+// null byte and its length is part of the name, so the used size is returned alongside
+#[cfg(target_os = "linux")]
+fn abstract_sockaddr(name: &[u8]) -> Result<(sockaddr_un, socklen_t)> {
+    let mut addr: sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = AF_UNIX as sa_family_t;
+
+    if name.len() >= addr.sun_path.len() {
+        return Err(SocketError::InvalidInput(
+            "Abstract socket name is too long",
+        ));
+    }
+    for (dst, &src) in addr.sun_path[1..].iter_mut().zip(name) {
+        *dst = src as c_char;
+    }
+
+    let len = mem::offset_of!(sockaddr_un, sun_path) + 1 + name.len();
+    Ok((addr, len as socklen_t))
+}
+
+// reads back a socket address the kernel filled in, e.g. from accept or getsockname, len is
+// the size it reported
+fn endpoint_from_storage(addr: &sockaddr_storage, len: socklen_t) -> Result<Endpoint> {
+    // a truncated address reports the size it would have needed, never read past storage
+    let len = len.min(mem::size_of::<sockaddr_storage>() as socklen_t);
+
+    match addr.ss_family as i32 {
+        AF_INET => {
+            let addr = unsafe { &*(addr as *const sockaddr_storage as *const sockaddr_in) };
+            let (ip, port) = ipv4_from_sockaddr(addr);
+            Ok(Endpoint::V4(ip, port))
+        }
+        AF_INET6 => {
+            let addr = unsafe { &*(addr as *const sockaddr_storage as *const sockaddr_in6) };
+            Ok(Endpoint::V6(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+            ))
+        }
+        AF_UNIX => {
+            let addr = unsafe { &*(addr as *const sockaddr_storage as *const sockaddr_un) };
+            // unnamed sockets report no path bytes at all
+            let path_len = (len as usize)
+                .saturating_sub(mem::offset_of!(sockaddr_un, sun_path))
+                .min(addr.sun_path.len());
+            let bytes: Vec<u8> = addr.sun_path[..path_len].iter().map(|&c| c as u8).collect();
+
+            // an abstract name starts with a null byte and may contain more of them
+            #[cfg(target_os = "linux")]
+            if bytes.first() == Some(&0) {
+                return Ok(Endpoint::Unix(PathBuf::from(OsStr::from_bytes(&bytes))));
+            }
+
+            // filesystem paths end at the first null byte
+            let path = bytes.split(|&b| b == 0).next().unwrap_or(&[]);
+            Ok(Endpoint::Unix(PathBuf::from(OsStr::from_bytes(path))))
+        }
+        _ => Err(SocketError::Unsupported("Unknown address family")),
+    }
+}
+
+// reorders addrs so the families alternate, starting with the family getaddrinfo put first,
+// otherwise a host with many IPv6 addresses would make IPv4 wait behind all of them
+fn interleave_families(addrs: Vec<Endpoint>) -> Vec<Endpoint> {
+    let first_v6 = matches!(addrs.first(), Some(Endpoint::V6(..)));
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| matches!(addr, Endpoint::V6(..)));
+    let (mut first, mut second) = if first_v6 { (v6, v4) } else { (v4, v6) };
+    first.reverse();
+    second.reverse();
+
+    let mut interleaved = Vec::with_capacity(first.len() + second.len());
+    while !first.is_empty() || !second.is_empty() {
+        interleaved.extend(first.pop());
+        interleaved.extend(second.pop());
+    }
+    interleaved
+}
+
+// a socket address for any supported family, ready to be passed to bind/connect
+enum SockAddr {
+    V4(sockaddr_in),
+    V6(sockaddr_in6),
+    Unix(sockaddr_un),
+    #[cfg(target_os = "linux")]
+    UnixAbstract(sockaddr_un, socklen_t),
+}
+
+impl SockAddr {
+    fn from_endpoint(endpoint: &Endpoint) -> Result<SockAddr> {
+        match endpoint {
+            Endpoint::V4(ip, port) => Ok(SockAddr::V4(ipv4_sockaddr(*ip, *port))),
+            Endpoint::V6(ip, port) => Ok(SockAddr::V6(ipv6_sockaddr(*ip, *port))),
+            // the form local_addr and peer_addr report abstract names in, see
+            // endpoint_from_storage
+            #[cfg(target_os = "linux")]
+            Endpoint::Unix(path) if path.as_os_str().as_bytes().first() == Some(&0) => {
+                let (addr, len) = abstract_sockaddr(&path.as_os_str().as_bytes()[1..])?;
+                Ok(SockAddr::UnixAbstract(addr, len))
+            }
+            Endpoint::Unix(path) => {
+                let path = path.to_str().ok_or(SocketError::InvalidInput(
+                    "Unix socket path is not valid UTF-8",
+                ))?;
+                Ok(SockAddr::Unix(unix_sockaddr(path)?))
+            }
+        }
+    }
+
+    fn as_ptr(&self) -> *const sockaddr {
+        match self {
+            SockAddr::V4(addr) => addr as *const sockaddr_in as *const sockaddr,
+            SockAddr::V6(addr) => addr as *const sockaddr_in6 as *const sockaddr,
+            SockAddr::Unix(addr) => addr as *const sockaddr_un as *const sockaddr,
+            #[cfg(target_os = "linux")]
+            SockAddr::UnixAbstract(addr, _) => addr as *const sockaddr_un as *const sockaddr,
+        }
+    }
+
+    fn size(&self) -> socklen_t {
+        match self {
+            SockAddr::V4(_) => mem::size_of::<sockaddr_in>() as socklen_t,
+            SockAddr::V6(_) => mem::size_of::<sockaddr_in6>() as socklen_t,
+            SockAddr::Unix(_) => mem::size_of::<sockaddr_un>() as socklen_t,
+            #[cfg(target_os = "linux")]
+            SockAddr::UnixAbstract(_, len) => *len,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum SocketState {
     Created,
     Bound,
@@ -68,184 +667,5759 @@ pub enum SocketState {
     Closed,
 }
 
+// which half of the connection to shut down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shutdown {
+    Read,
+    Write,
+    Both,
+}
+
+impl Shutdown {
+    fn as_raw(self) -> i32 {
+        match self {
+            Shutdown::Read => SHUT_RD,
+            Shutdown::Write => SHUT_WR,
+            Shutdown::Both => SHUT_RDWR,
+        }
+    }
+}
+
+// how the kernel handles the Don't Fragment bit, see Socket::set_mtu_discover
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MtuDiscover {
+    // set DF and fail sends larger than the path MTU with EMSGSIZE
+    Do,
+    // never set DF, oversized packets get fragmented
+    Dont,
+    // set DF but fragment locally once the path MTU is known, the default
+    Want,
+}
+
+impl MtuDiscover {
+    #[cfg(target_os = "linux")]
+    fn as_raw(self) -> c_int {
+        match self {
+            MtuDiscover::Do => libc::IP_PMTUDISC_DO,
+            MtuDiscover::Dont => libc::IP_PMTUDISC_DONT,
+            MtuDiscover::Want => libc::IP_PMTUDISC_WANT,
+        }
+    }
+}
+
+// how an idle connection is probed, see Socket::set_keepalive, the kernel works in whole
+// seconds so each time is rounded up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepaliveParams {
+    // how long the connection must be idle before the first probe
+    pub idle: Duration,
+    // time between unanswered probes
+    pub interval: Duration,
+    // unanswered probes before the connection is dropped
+    pub retries: u32,
+}
+
+// the process on the other end of a Unix domain socket, as recorded when it connected (or
+// when the pair was created), not necessarily who is using the socket now
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// totals of the payload bytes moved through a socket, see Socket::stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+// a snapshot of the kernel's view of a TCP connection, see Socket::tcp_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpInfo {
+    // the kernel's TCP state, 1 (TCP_ESTABLISHED) for a working connection
+    pub state: u8,
+    // smoothed round trip time and its variance
+    pub rtt: Duration,
+    pub rtt_var: Duration,
+    // retransmissions of the segment currently waiting for an ack
+    pub retransmits: u8,
+    // retransmissions over the life of the connection
+    pub total_retransmits: u32,
+    // congestion window, in segments of snd_mss bytes
+    pub snd_cwnd: u32,
+    pub snd_mss: u32,
+    // segments sent but not acked yet, and those of them the kernel considers lost
+    pub unacked: u32,
+    pub lost: u32,
+}
+
+// atomics so the counters can be bumped through &self, they saturate instead of wrapping
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, bytes: usize) {
+        let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+            Some(total.saturating_add(bytes as u64))
+        });
+    }
+}
+
 pub struct Socket {
     fd: RawFd,
     state: SocketState,
+    family: i32,
+    sock_type: i32,
+    counters: Counters,
+    // the peer accept already learned, saves peer_addr a getpeername
+    peer: Option<Endpoint>,
+    max_frame_len: usize,
+    // read end of the pipe a ShutdownHandle writes to, see Socket::shutdown_handle
+    wakeup: Option<Arc<Wakeup>>,
 }
 
 impl Socket {
-    pub fn new() -> Result<Self, String> {
-        let fd = unsafe { socket(AF_INET, SOCK_STREAM, 0) };
+    pub fn new() -> Result<Self> {
+        Self::open(AF_INET, SOCK_STREAM)
+    }
 
-        if fd == -1 {
-            Err("Failed to create a socket".into())
-        } else {
-            Ok(Socket {
-                fd,
-                state: SocketState::Created,
-            })
-        }
+    // creates an IPv6 TCP socket
+    pub fn new_v6() -> Result<Self> {
+        Self::open(AF_INET6, SOCK_STREAM)
     }
 
-    pub fn bind(&mut self, ip: &str, port: u16) -> Result<(), String> {
-        if self.state != SocketState::Created {
-            return Err("Socket already bound our connected".into());
+    // creates a Unix domain stream socket, use bind_unix/connect_unix to give it an address
+    pub fn new_unix() -> Result<Self> {
+        Self::open(AF_UNIX, SOCK_STREAM)
+    }
+
+    // creates an IPv4 UDP socket, usable with send_to/recv_from once bound
+    pub fn new_udp() -> Result<Self> {
+        Self::open(AF_INET, SOCK_DGRAM)
+    }
+
+    // sockets are close-on-exec from the start so they don't leak into child processes
+    fn open(family: i32, sock_type: i32) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let fd = unsafe { socket(family, sock_type | libc::SOCK_CLOEXEC, 0) };
+        #[cfg(not(target_os = "linux"))]
+        let fd = unsafe { socket(family, sock_type, 0) };
+
+        if fd == -1 {
+            return Err(SocketError::last_os_error());
         }
-        let ip: Ipv4Addr = ip.parse().map_err(|_| "Ivalid IP address")?;
-        // create IPv4 address
-        // TODO: make portable to support different platforms
-        let addr = sockaddr_in {
-            sin_len: mem::size_of::<sockaddr_in>() as u8, // length of the socket address strcut itself - only used on macOS
-            sin_family: AF_INET as u8, // IPv4 address family (u8 on MacOS, u16 on Linux)
-            sin_port: port.to_be(),    // port in big-endian notation
-            sin_addr: in_addr {
-                s_addr: u32::from(ip).to_be(),
-            }, // address to bind to INADDR_ANY - all addresses 0.0.0.0
-            sin_zero: [0; 8],          // padding initalized to zero's
+
+        let socket = Socket {
+            fd,
+            state: SocketState::Created,
+            family,
+            sock_type,
+            counters: Counters::default(),
+            peer: None,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            wakeup: None,
         };
+        socket.init()?;
 
+        Ok(socket)
+    }
+
+    // two Unix domain stream sockets already connected to each other, handy for IPC with a
+    // child process or threads, and for tests that need a connection without any addresses
+    pub fn pair() -> Result<(Socket, Socket)> {
+        let mut fds = [0; 2];
+
+        #[cfg(target_os = "linux")]
         let res = unsafe {
-            bind(
-                self.fd,
-                &addr as *const sockaddr_in as *const sockaddr,
-                mem::size_of::<sockaddr_in>() as u32,
+            socketpair(
+                AF_UNIX,
+                SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
             )
         };
+        #[cfg(not(target_os = "linux"))]
+        let res = unsafe { socketpair(AF_UNIX, SOCK_STREAM, 0, fds.as_mut_ptr()) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        let [first, second] = fds.map(|fd| Socket {
+            fd,
+            state: SocketState::Connected,
+            family: AF_UNIX,
+            sock_type: SOCK_STREAM,
+            counters: Counters::default(),
+            peer: None,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            wakeup: None,
+        });
+        first.init()?;
+        second.init()?;
+
+        Ok((first, second))
+    }
+
+    // setup Linux gets atomically from SOCK_CLOEXEC and MSG_NOSIGNAL, other platforms apply
+    // it right after creating the descriptor, leaving a short window where a concurrent exec
+    // inherits it
+    fn init(&self) -> Result<()> {
+        #[cfg(not(target_os = "linux"))]
+        self.set_cloexec(true)?;
+        #[cfg(target_os = "macos")]
+        self.set_int_option(SOL_SOCKET, libc::SO_NOSIGPIPE, 1)?;
+
+        Ok(())
+    }
+
+    pub fn state(&self) -> &SocketState {
+        &self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == SocketState::Connected
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.state == SocketState::Listening
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == SocketState::Closed
+    }
+
+    // converts addr and checks it matches the family the socket was created with, the kernel
+    // would reject a mismatch too but with a vaguer EAFNOSUPPORT/EINVAL
+    fn sockaddr(&self, addr: &impl ToEndpoint) -> Result<SockAddr> {
+        let endpoint = addr.to_endpoint()?;
+        let family = match endpoint {
+            Endpoint::V4(..) => AF_INET,
+            Endpoint::V6(..) => AF_INET6,
+            Endpoint::Unix(_) => AF_UNIX,
+        };
+        if family != self.family {
+            return Err(SocketError::InvalidInput(
+                "Address does not match the socket address family",
+            ));
+        }
+        SockAddr::from_endpoint(&endpoint)
+    }
+
+    // addr is anything ToEndpoint accepts, e.g. ("127.0.0.1", 8080) or a std SocketAddr
+    pub fn bind(&mut self, addr: impl ToEndpoint) -> Result<()> {
+        let addr = self.sockaddr(&addr)?;
+        self.bind_sockaddr(&addr)
+    }
+
+    // binds a Unix domain socket to a filesystem path, the caller removes the file afterwards
+    pub fn bind_unix(&mut self, path: &str) -> Result<()> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        let addr = unix_sockaddr(path)?;
+        self.bind_sockaddr(&SockAddr::Unix(addr))
+    }
+
+    // binds a Unix domain socket to a Linux abstract name, which leaves no file behind
+    #[cfg(target_os = "linux")]
+    pub fn bind_abstract(&mut self, name: &str) -> Result<()> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        let (addr, len) = abstract_sockaddr(name.as_bytes())?;
+        self.bind_sockaddr(&SockAddr::UnixAbstract(addr, len))
+    }
+
+    fn bind_sockaddr(&mut self, addr: &SockAddr) -> Result<()> {
+        if self.state != SocketState::Created {
+            return Err(SocketError::InvalidState(
+                "Socket already bound or connected",
+            ));
+        }
+
+        let res = unsafe { bind(self.fd, addr.as_ptr(), addr.size()) };
 
         if res == -1 {
-            return Err("Failed to bind socket".into());
+            return Err(SocketError::last_os_error());
         }
 
         self.state = SocketState::Bound;
         Ok(())
     }
 
-    pub fn listen(&mut self, backlog: i32) -> Result<(), String> {
+    pub fn listen(&mut self, backlog: i32) -> Result<()> {
         if self.state != SocketState::Bound {
-            return Err("Socket must be bound before listening".into());
+            return Err(SocketError::InvalidState(
+                "Socket must be bound before listening",
+            ));
         }
 
         let res = unsafe { listen(self.fd, backlog) };
 
         if res == -1 {
-            return Err("Failed to listen on socket".into());
+            return Err(SocketError::last_os_error());
         }
 
         self.state = SocketState::Listening;
         Ok(())
     }
 
-    pub fn accept(&self) -> Result<Socket, String> {
+    // returns the connected socket plus the peer's address
+    pub fn accept(&self) -> Result<(Socket, Endpoint)> {
+        self.accept_with(false, true)
+    }
+
+    // like accept, but gives up with None once timeout passes without a connection, a zero
+    // timeout just checks, the peer address stays available through peer_addr
+    pub fn accept_timeout(&self, timeout: Duration) -> Result<Option<Socket>> {
+        if self.state != SocketState::Listening {
+            return Err(SocketError::InvalidState("Socket is not listening"));
+        }
+
+        if !self.wait_for_connection(Some(timeout))? {
+            return Ok(None);
+        }
+
+        let (socket, _) = self.accept()?;
+        Ok(Some(socket))
+    }
+
+    // like accept, but picks the non-blocking and close-on-exec modes of the new socket, on
+    // Linux accept4 applies them atomically, elsewhere they are set right after accepting
+    pub fn accept_with(&self, nonblocking: bool, cloexec: bool) -> Result<(Socket, Endpoint)> {
         if self.state != SocketState::Listening {
-            return Err("Socket is not listening".into());
+            return Err(SocketError::InvalidState("Socket is not listening"));
         }
 
-        let client_fd = unsafe { accept(self.fd, std::ptr::null_mut(), std::ptr::null_mut()) };
+        // with a shutdown handle the wait moves into poll so the handle can interrupt it, the
+        // listener's own non-blocking mode and read timeout still apply
+        if self.wakeup.is_some() {
+            let listener_nonblocking = self.nonblocking()?;
+            let timeout = if listener_nonblocking {
+                Some(Duration::ZERO)
+            } else {
+                self.recv_timeout()?
+            };
+
+            if !self.wait_for_connection(timeout)? {
+                return Err(if listener_nonblocking {
+                    SocketError::WouldBlock
+                } else {
+                    SocketError::TimedOut
+                });
+            }
+        }
+
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+        let addr_ptr = &mut addr as *mut sockaddr_storage as *mut sockaddr;
+        let len_ptr = &mut addr_len as *mut socklen_t;
+
+        #[cfg(target_os = "linux")]
+        let client_fd = {
+            let mut flags = 0;
+            if nonblocking {
+                flags |= libc::SOCK_NONBLOCK;
+            }
+            if cloexec {
+                flags |= libc::SOCK_CLOEXEC;
+            }
+            unsafe { accept4(self.fd, addr_ptr, len_ptr, flags) }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let client_fd = unsafe { accept(self.fd, addr_ptr, len_ptr) };
 
         if client_fd == -1 {
-            return Err("Failed to accept connection".into());
+            return Err(self.last_error());
         }
 
-        Ok(Socket {
+        let mut socket = Socket {
             fd: client_fd,
             state: SocketState::Connected,
-        })
+            family: self.family,
+            sock_type: self.sock_type,
+            counters: Counters::default(),
+            peer: None,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            wakeup: None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        {
+            socket.set_nonblocking(nonblocking)?;
+            socket.set_cloexec(cloexec)?;
+        }
+        #[cfg(target_os = "macos")]
+        socket.set_int_option(SOL_SOCKET, libc::SO_NOSIGPIPE, 1)?;
+
+        let peer = endpoint_from_storage(&addr, addr_len)?;
+        socket.peer = Some(peer.clone());
+        Ok((socket, peer))
     }
 
-    pub fn connect(&mut self, ip: &str, port: u16) -> Result<(), String> {
-        if self.state != SocketState::Created {
-            return Err("Socket already bound or connected".into());
-        }
-        let ip: Ipv4Addr = ip.parse().map_err(|_| "Ivalid IP address")?;
-        // create IPv4 address
-        // TODO: make portable to support different platforms
-        let addr = sockaddr_in {
-            sin_len: mem::size_of::<sockaddr_in>() as u8, // length of the socket address strcut itself - only used on macOS
-            sin_family: AF_INET as u8, // IPv4 address family (u8 on MacOS, u16 on Linux)
-            sin_port: port.to_be(),    // port in big-endian notation
-            sin_addr: in_addr {
-                s_addr: u32::from(ip).to_be(),
-            }, // address to bind to INADDR_ANY - all addresses 0.0.0.0
-            sin_zero: [0; 8],          // padding initalized to zero's
-        };
+    // accepts connections in a loop, like std::net::TcpListener::incoming
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
 
-        let res = unsafe {
-            connect(
-                self.fd,
-                &addr as *const sockaddr_in as *const sockaddr,
-                mem::size_of::<sockaddr_in>() as *const u32,
-            )
-        };
+    // accepts connections and hands each to handler until max have been served, forever
+    // when max is None, a client that gave up before being accepted is skipped, any other
+    // accept error ends the loop
+    pub fn serve<F: FnMut(Socket)>(&self, max: Option<usize>, mut handler: F) -> Result<()> {
+        if self.state != SocketState::Listening {
+            return Err(SocketError::InvalidState("Socket is not listening"));
+        }
 
-        if res == -1 {
-            return Err("Failed to connect to address".into());
+        let mut served = 0;
+        while max.is_none_or(|max| served < max) {
+            match self.accept() {
+                Ok((socket, _)) => handler(socket),
+                Err(SocketError::Os(ECONNABORTED)) => continue,
+                Err(SocketError::Shutdown) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            served += 1;
         }
 
-        self.state = SocketState::Connected;
         Ok(())
     }
-}
 
-impl Drop for Socket {
-    fn drop(&mut self) {
-        if self.state != SocketState::Closed {
-            unsafe { close(self.fd); }
-            self.state = SocketState::Closed;
+    // returns a handle another thread can use to stop this listener: serve returns Ok and
+    // accept, accept_with and accept_timeout, including calls already blocked in them, fail
+    // with SocketError::Shutdown, every call hands out a handle to the same signal and the
+    // shutdown is permanent, every later accept on the listener (or a try_clone of it) fails
+    // the same way
+    pub fn shutdown_handle(&mut self) -> Result<ShutdownHandle> {
+        if self.state != SocketState::Listening {
+            return Err(SocketError::InvalidState("Socket is not listening"));
         }
+
+        let wakeup = match &self.wakeup {
+            Some(wakeup) => wakeup.clone(),
+            None => {
+                let wakeup = Arc::new(Wakeup::new()?);
+                self.wakeup = Some(wakeup.clone());
+                wakeup
+            }
+        };
+        Ok(ShutdownHandle { wakeup })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // waits until a connection can be accepted, false once timeout passed without one (None
+    // waits forever), fails if the shutdown handle fired
+    fn wait_for_connection(&self, timeout: Option<Duration>) -> Result<bool> {
+        let Some(wakeup) = &self.wakeup else {
+            return self.poll_readable(timeout);
+        };
 
-    #[test]
-    fn test_can_create_socket() {
-        let sock = Socket::new();
-        assert_eq!(
-            sock.is_ok(),
-            true,
-            "retured a file descriptor with a value of -1"
-        );
-    }
+        let deadline = timeout.map(|dur| Instant::now() + dur);
+        loop {
+            let mut fds = [
+                pollfd {
+                    fd: self.fd,
+                    events: POLLIN,
+                    revents: 0,
+                },
+                pollfd {
+                    fd: wakeup.read_fd,
+                    events: POLLIN,
+                    revents: 0,
+                },
+            ];
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    poll_timeout_ms(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => -1,
+            };
+            let res = unsafe { poll(fds.as_mut_ptr(), fds.len() as nfds_t, timeout_ms) };
 
-    #[test]
-    fn test_bind_socket_to_port() {
-        let mut sock = Socket::new().expect("Failed to create socket");
-        // use 0 to allow the use to chose an avaiable ephepermal port
-        let _ = sock.bind("0.0.0.0", 0);
-        // close the socket after use
-        unsafe {
-            close(sock.fd);
+            if res == -1 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(SocketError::last_os_error());
+            }
+
+            // the byte is never read back, so every waiter sees the shutdown
+            if fds[1].revents != 0 {
+                return Err(SocketError::Shutdown);
+            }
+            return Ok(res > 0);
         }
     }
 
-    #[test]
-    fn test_bind_socket_invalid_fd() {
-        // passing invalid socket descriptor
-        let mut sock = Socket::new().expect("Failed to create socket");
-        // use 0 to allow the use to chose an avaiable ephepermal port
-        let res = sock.bind("-dvddfvfdvdvd0.0.0.0", 0);
+    // the SO_RCVTIMEO set with set_read_timeout, None when there is none
+    fn recv_timeout(&self) -> Result<Option<Duration>> {
+        let tv: timeval = self.get_option(SOL_SOCKET, SO_RCVTIMEO)?;
+        let dur = Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+        Ok(Some(dur).filter(|dur| !dur.is_zero()))
+    }
 
-        assert_eq!(res.is_err(), true, "Should fail to bind scoket")
+    pub fn connect(&mut self, addr: impl ToEndpoint) -> Result<()> {
+        let addr = self.sockaddr(&addr)?;
+        self.connect_sockaddr(&addr)
     }
 
-    #[test]
-    fn test_bind_socket_port_in_use() {
-        let mut sock_1 = Socket::new().expect("Failed to create socket");
-        let mut sock_2 = Socket::new().expect("Failed to create socket");
+    // connects and sends data with TCP Fast Open, when the client holds a cookie from an
+    // earlier connection to a set_fastopen listener the data rides along with the SYN and
+    // saves a round trip, otherwise it goes out right after the handshake, returns how many
+    // bytes of data were queued, on Linux net.ipv4.tcp_fastopen has to allow client use
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn connect_fastopen(&mut self, addr: impl ToEndpoint, data: &[u8]) -> Result<usize> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP Fast Open only applies to stream sockets",
+            ));
+        }
+        if self.state != SocketState::Created && self.state != SocketState::Bound {
+            return Err(SocketError::InvalidState(
+                "Socket already listening or connected",
+            ));
+        }
+        let addr = self.sockaddr(&addr)?;
 
-        // bind first soccket
-        let res1 = sock_1.bind("0.0.0.0", 1150);
-        // bind second sock to the same port
-        let res2 = sock_2.bind("0.0.0.0", 1150);
+        let sent = self.fastopen(&addr, data)?;
+        self.state = SocketState::Connected;
+        Counters::add(&self.counters.sent, sent);
+        Ok(sent)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn connect_fastopen(&mut self, _addr: impl ToEndpoint, _data: &[u8]) -> Result<usize> {
+        Err(SocketError::Unsupported(
+            "TCP Fast Open is not supported on this platform",
+        ))
+    }
+
+    // sendto with MSG_FASTOPEN connects an unconnected socket as a side effect
+    #[cfg(target_os = "linux")]
+    fn fastopen(&self, addr: &SockAddr, data: &[u8]) -> Result<usize> {
+        let res = unsafe {
+            sendto(
+                self.fd,
+                data.as_ptr(),
+                data.len(),
+                libc::MSG_FASTOPEN | SEND_FLAGS,
+                addr.as_ptr(),
+                addr.size(),
+            )
+        };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Ok(res as usize)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn fastopen(&self, addr: &SockAddr, data: &[u8]) -> Result<usize> {
+        let mut endpoints: libc::sa_endpoints_t = unsafe { mem::zeroed() };
+        endpoints.sae_dstaddr = addr.as_ptr();
+        endpoints.sae_dstaddrlen = addr.size();
+        let iov = iovec {
+            iov_base: data.as_ptr() as *mut c_void,
+            iov_len: data.len(),
+        };
+        let mut len = 0;
+
+        let res = unsafe {
+            connectx(
+                self.fd,
+                &endpoints,
+                libc::SAE_ASSOCID_ANY,
+                libc::CONNECT_RESUME_ON_READ_WRITE | libc::CONNECT_DATA_IDEMPOTENT,
+                &iov,
+                1,
+                &mut len,
+                ptr::null_mut(),
+            )
+        };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Ok(len)
+    }
+
+    // resolves host and tries each of its addresses until one accepts the connection
+    // a failed connect leaves the socket unusable on some platforms, so an unbound socket is
+    // recreated between attempts and options set on it beforehand only apply to the first one
+    pub fn connect_host(&mut self, host: &str, port: u16) -> Result<()> {
+        if self.family != AF_INET {
+            return Err(SocketError::InvalidInput(
+                "connect_host only supports IPv4 sockets",
+            ));
+        }
+
+        let mut last_err = SocketError::InvalidInput("Host did not resolve to any address");
+
+        for (attempt, endpoint) in resolve(host, port)?.into_iter().enumerate() {
+            if attempt > 0 && self.state == SocketState::Created {
+                *self = Socket::open(self.family, self.sock_type)?;
+            }
+
+            match self.connect_sockaddr(&SockAddr::from_endpoint(&endpoint)?) {
+                Ok(()) => return Ok(()),
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    // connects to host over IPv4 or IPv6, whichever answers first (RFC 8305): the addresses
+    // are tried alternating by family, each getting CONNECTION_ATTEMPT_DELAY before the next
+    // one starts alongside it, the first to finish wins and the rest are closed, self is
+    // replaced by a socket of the winning family, so options set on it beforehand are lost
+    pub fn connect_happy_eyeballs(
+        &mut self,
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<()> {
+        if self.sock_type != SOCK_STREAM || self.family == AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Happy eyeballs only applies to TCP sockets",
+            ));
+        }
+        if self.state != SocketState::Created {
+            return Err(SocketError::InvalidState(
+                "Socket already bound, listening or connected",
+            ));
+        }
+        if timeout.is_zero() {
+            return Err(SocketError::InvalidInput(
+                "Timeout must be greater than zero",
+            ));
+        }
+
+        let deadline = Instant::now() + timeout;
+        let addrs = interleave_families(resolve_dual(host, port)?);
+        let mut next = 0;
+        let mut pending: Vec<Socket> = Vec::new();
+        let mut next_start = Instant::now();
+        let mut last_err = SocketError::InvalidInput("Host did not resolve to any address");
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(SocketError::TimedOut);
+            }
+
+            if next < addrs.len() && (pending.is_empty() || now >= next_start) {
+                let endpoint = &addrs[next];
+                next += 1;
+                match Socket::start_connect(endpoint) {
+                    Ok((socket, true)) => return self.finish_happy_eyeballs(socket),
+                    Ok((socket, false)) => {
+                        pending.push(socket);
+                        next_start = now + CONNECTION_ATTEMPT_DELAY;
+                    }
+                    Err(err) => last_err = err,
+                }
+                continue;
+            }
+            if pending.is_empty() {
+                return Err(last_err);
+            }
+
+            let wait_until = if next < addrs.len() {
+                next_start.min(deadline)
+            } else {
+                deadline
+            };
+            let mut fds: Vec<pollfd> = pending
+                .iter()
+                .map(|socket| pollfd {
+                    fd: socket.fd,
+                    events: POLLOUT,
+                    revents: 0,
+                })
+                .collect();
+            let timeout_ms = poll_timeout_ms(wait_until.saturating_duration_since(now));
+            let res = unsafe { poll(fds.as_mut_ptr(), fds.len() as nfds_t, timeout_ms) };
+
+            if res == -1 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(SocketError::last_os_error());
+            }
+
+            // walk backwards so removing an attempt doesn't shift the ones still to check
+            for i in (0..fds.len()).rev() {
+                if fds[i].revents == 0 {
+                    continue;
+                }
+                let mut socket = pending.remove(i);
+                match socket.take_connect_error() {
+                    Ok(()) => return self.finish_happy_eyeballs(socket),
+                    Err(SocketError::InProgress) => pending.push(socket),
+                    Err(err) => {
+                        last_err = err;
+                        // a failed attempt lets the next address start right away
+                        next_start = Instant::now();
+                    }
+                }
+            }
+        }
+    }
+
+    // opens a non-blocking socket for endpoint and starts connecting, true when the connect
+    // already finished
+    fn start_connect(endpoint: &Endpoint) -> Result<(Socket, bool)> {
+        let family = match endpoint {
+            Endpoint::V6(..) => AF_INET6,
+            _ => AF_INET,
+        };
+        let mut socket = Socket::open(family, SOCK_STREAM)?;
+        socket.set_nonblocking(true)?;
+
+        match socket.connect_sockaddr(&SockAddr::from_endpoint(endpoint)?) {
+            Ok(()) => Ok((socket, true)),
+            Err(SocketError::InProgress) => Ok((socket, false)),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn finish_happy_eyeballs(&mut self, socket: Socket) -> Result<()> {
+        socket.set_nonblocking(false)?;
+        *self = socket;
+        Ok(())
+    }
+
+    // like connect but retries a refused or failed attempt, for a service that is still
+    // starting, sleeping base_delay, then twice that, and so on between tries, gives up
+    // after attempts tries with the last error, 0 attempts still makes one try
+    pub fn connect_retry(
+        &mut self,
+        addr: impl ToEndpoint,
+        attempts: u32,
+        base_delay: Duration,
+    ) -> Result<()> {
+        let addr = self.sockaddr(&addr)?;
+        let attempts = attempts.max(1);
+        let mut delay = base_delay;
+        let mut attempt = 1;
+
+        loop {
+            // a failed connect leaves the socket unusable on some platforms
+            if attempt > 1 && self.state == SocketState::Created {
+                *self = Socket::open(self.family, self.sock_type)?;
+            }
+
+            match self.connect_sockaddr(&addr) {
+                Ok(()) => return Ok(()),
+                // retrying can't fix a socket in the wrong state
+                Err(err @ SocketError::InvalidState(_)) => return Err(err),
+                Err(err) if attempt == attempts => return Err(err),
+                Err(_) => {}
+            }
+
+            std::thread::sleep(delay);
+            delay = delay.saturating_mul(2);
+            attempt += 1;
+        }
+    }
+
+    // like connect but gives up with TimedOut once timeout passes, the socket is left in
+    // blocking mode afterwards
+    pub fn connect_timeout(&mut self, addr: impl ToEndpoint, timeout: Duration) -> Result<()> {
+        if timeout.is_zero() {
+            return Err(SocketError::InvalidInput(
+                "Timeout must be greater than zero",
+            ));
+        }
+        let addr = self.sockaddr(&addr)?;
+
+        self.set_nonblocking(true)?;
+        let res = match self.connect_sockaddr(&addr) {
+            Err(SocketError::InProgress) => self.finish_connect(timeout),
+            res => res,
+        };
+        self.set_nonblocking(false)?;
+
+        res
+    }
+
+    // waits for an in-progress non-blocking connect and reports how it ended
+    fn finish_connect(&mut self, timeout: Duration) -> Result<()> {
+        if !self.poll_events(POLLOUT, Some(timeout))? {
+            return Err(SocketError::TimedOut);
+        }
+
+        self.take_connect_error()
+    }
+
+    // returns and clears the pending asynchronous error (SO_ERROR), e.g. an ICMP port
+    // unreachable reported to a connected UDP socket, like std's TcpStream::take_error
+    pub fn take_error(&self) -> Result<Option<SocketError>> {
+        match self.get_int_option(SOL_SOCKET, SO_ERROR)? {
+            0 => Ok(None),
+            code => Ok(Some(SocketError::from_errno(code))),
+        }
+    }
+
+    // reports how a non-blocking connect that returned InProgress ended, call it once the
+    // socket polls writable, InProgress again means the handshake is still running
+    pub fn take_connect_error(&mut self) -> Result<()> {
+        if self.state == SocketState::Connected {
+            return Ok(());
+        }
+
+        if let Some(err) = self.take_error()? {
+            return Err(err);
+        }
+
+        // no error is also what an unfinished handshake looks like, only a peer tells them apart
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+        let res = unsafe {
+            getpeername(
+                self.fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::InProgress);
+        }
+
+        self.state = SocketState::Connected;
+        Ok(())
+    }
+
+    // connects a Unix domain socket to a listener bound at path
+    pub fn connect_unix(&mut self, path: &str) -> Result<()> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        let addr = unix_sockaddr(path)?;
+        self.connect_sockaddr(&SockAddr::Unix(addr))
+    }
+
+    // connects a Unix domain socket to a listener bound with bind_abstract
+    #[cfg(target_os = "linux")]
+    pub fn connect_abstract(&mut self, name: &str) -> Result<()> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        let (addr, len) = abstract_sockaddr(name.as_bytes())?;
+        self.connect_sockaddr(&SockAddr::UnixAbstract(addr, len))
+    }
+
+    // a bound socket can still connect, which pins the local port used for the connection,
+    // a datagram socket only records a default peer and may connect again to replace it
+    fn connect_sockaddr(&mut self, addr: &SockAddr) -> Result<()> {
+        let reconnect = self.sock_type == SOCK_DGRAM && self.state == SocketState::Connected;
+        if self.state != SocketState::Created && self.state != SocketState::Bound && !reconnect {
+            return Err(SocketError::InvalidState(
+                "Socket already listening or connected",
+            ));
+        }
+
+        let res = unsafe { connect(self.fd, addr.as_ptr(), addr.size()) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        self.state = SocketState::Connected;
+        Ok(())
+    }
+
+    // dissolves the default peer of a connected datagram socket, so it receives from anyone
+    // again and needs send_to, Linux releases a local port the kernel picked itself, only a
+    // specific port passed to bind survives
+    pub fn disconnect(&mut self) -> Result<()> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        // connecting to an AF_UNSPEC address is the defined way to drop the association
+        let mut addr: sockaddr = unsafe { mem::zeroed() };
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+        {
+            addr.sa_len = mem::size_of::<sockaddr>() as u8;
+        }
+        addr.sa_family = AF_UNSPEC as sa_family_t;
+
+        let res = unsafe { connect(self.fd, &addr, mem::size_of::<sockaddr>() as socklen_t) };
+
+        // the BSDs drop the association but still report EAFNOSUPPORT
+        if res == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(EAFNOSUPPORT) {
+                return Err(SocketError::from_errno(err.raw_os_error().unwrap_or(0)));
+            }
+        }
+
+        self.peer = None;
+        self.state = match self.local_addr()?.port() {
+            Some(0) => SocketState::Created,
+            _ => SocketState::Bound,
+        };
+        Ok(())
+    }
+
+    // returns the number of bytes actually written, which can be less than buf.len()
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.send_with_flags(buf, 0)
+    }
+
+    // sends a single byte of urgent data, e.g. a Telnet interrupt, the peer reads it with
+    // recv_oob unless it has SO_OOBINLINE set
+    pub fn send_oob(&self, byte: u8) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "Out-of-band data only applies to stream sockets",
+            ));
+        }
+        self.send_with_flags(&[byte], MSG_OOB)?;
+        Ok(())
+    }
+
+    fn send_with_flags(&self, buf: &[u8], flags: i32) -> Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let res = unsafe { send(self.fd, buf.as_ptr(), buf.len(), flags | SEND_FLAGS) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Counters::add(&self.counters.sent, res as usize);
+        Ok(res as usize)
+    }
+
+    // keeps sending until the whole buffer is written
+    pub fn send_all(&self, buf: &[u8]) -> Result<()> {
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            offset += self.send(&buf[offset..])?;
+        }
+
+        Ok(())
+    }
+
+    // writes several buffers with a single syscall, e.g. a header and a body without first
+    // concatenating them, only the first IOV_MAX buffers are sent
+    pub fn send_vectored(&self, bufs: &[&[u8]]) -> Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let iov: Vec<iovec> = bufs
+            .iter()
+            .take(IOV_MAX)
+            .map(|buf| iovec {
+                iov_base: buf.as_ptr() as *mut c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+
+        // msghdr has private padding on some platforms, so start from all zeroes
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = iov.as_ptr() as *mut iovec;
+        msg.msg_iovlen = iov.len() as _;
+
+        let res = unsafe { sendmsg(self.fd, &msg, SEND_FLAGS) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Counters::add(&self.counters.sent, res as usize);
+        Ok(res as usize)
+    }
+
+    // copies up to count bytes of file, starting at offset, to the peer without passing them
+    // through user space, returns how many were sent, which can be less than count, the
+    // file's own read position is left untouched
+    pub fn send_file(&self, file: &File, offset: u64, count: usize) -> Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+        let offset = off_t::try_from(offset)
+            .map_err(|_| SocketError::InvalidInput("File offset is too large"))?;
+
+        // macOS treats a count of 0 as "until the end of the file"
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let sent = self.sendfile(file.as_raw_fd(), offset, count)?;
+        Counters::add(&self.counters.sent, sent);
+        Ok(sent)
+    }
 
-        assert_eq!(res1.is_ok(), true, "Failed to bind socket to port");
-        assert_ne!(res2.is_ok(), true, "Bound socket to port successfully");
+    #[cfg(target_os = "linux")]
+    fn sendfile(&self, file_fd: RawFd, offset: off_t, count: usize) -> Result<usize> {
+        // sendfile takes no MSG_NOSIGNAL, a reset peer would raise SIGPIPE and kill the process
+        let _sigpipe = SigpipeBlock::new()?;
+        let mut offset = offset;
+        let res = unsafe { sendfile(self.fd, file_fd, &mut offset, count) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Ok(res as usize)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn sendfile(&self, file_fd: RawFd, offset: off_t, count: usize) -> Result<usize> {
+        let mut len = off_t::try_from(count).unwrap_or(off_t::MAX);
+        let res = unsafe { sendfile(file_fd, self.fd, offset, &mut len, ptr::null_mut(), 0) };
+
+        // a transfer cut short by a timeout or signal fails but still reports what it sent
+        if res == -1 && len == 0 {
+            return Err(self.last_error());
+        }
+
+        Ok(len as usize)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn sendfile(&self, _file_fd: RawFd, _offset: off_t, _count: usize) -> Result<usize> {
+        Err(SocketError::Unsupported(
+            "sendfile is not supported on this platform",
+        ))
+    }
+
+    // reads into several buffers with a single syscall, filling each before moving to the next
+    pub fn recv_vectored(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let iov: Vec<iovec> = bufs
+            .iter_mut()
+            .take(IOV_MAX)
+            .map(|buf| iovec {
+                iov_base: buf.as_mut_ptr() as *mut c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+
+        let res = unsafe { readv(self.fd, iov.as_ptr(), iov.len() as c_int) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Counters::add(&self.counters.received, res as usize);
+        Ok(res as usize)
+    }
+
+    // hands a copy of fd to the process on the other end of a Unix domain socket, fd stays
+    // open here and can be closed once this returns
+    pub fn send_fd(&self, fd: RawFd) -> Result<()> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        // ancillary data has to ride along with at least one byte of regular data
+        let byte = [0u8; 1];
+        let iov = iovec {
+            iov_base: byte.as_ptr() as *mut c_void,
+            iov_len: byte.len(),
+        };
+        let mut control = [0u64; FD_CONTROL_WORDS];
+
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &iov as *const iovec as *mut iovec;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = unsafe { CMSG_SPACE(mem::size_of::<c_int>() as u32) } as _;
 
         unsafe {
-            close(sock_1.fd);
-            close(sock_2.fd);
+            let cmsg = CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = SOL_SOCKET;
+            (*cmsg).cmsg_type = SCM_RIGHTS;
+            (*cmsg).cmsg_len = CMSG_LEN(mem::size_of::<c_int>() as u32) as _;
+            ptr::write_unaligned(CMSG_DATA(cmsg) as *mut c_int, fd);
+        }
+
+        let res = unsafe { sendmsg(self.fd, &msg, SEND_FLAGS) };
+
+        if res == -1 {
+            return Err(self.last_error());
         }
+
+        Ok(())
+    }
+
+    // receives a descriptor sent with send_fd, the caller owns it and must close it, it is
+    // close-on-exec like the sockets this crate creates, any further descriptors in the same
+    // message are closed
+    pub fn recv_fd(&self) -> Result<RawFd> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let mut byte = [0u8; 1];
+        let mut iov = iovec {
+            iov_base: byte.as_mut_ptr() as *mut c_void,
+            iov_len: byte.len(),
+        };
+        let mut control = [0u64; FD_CONTROL_WORDS];
+
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        #[cfg(target_os = "linux")]
+        let res = unsafe { recvmsg(self.fd, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        #[cfg(not(target_os = "linux"))]
+        let res = unsafe { recvmsg(self.fd, &mut msg, 0) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+        if res == 0 {
+            return Err(SocketError::UnexpectedEof);
+        }
+
+        // every descriptor the peer sent is already installed here, keep the first and close
+        // the rest so none of them leak
+        let mut fds = Vec::new();
+        let mut cmsg = unsafe { CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let (level, kind, len) = unsafe {
+                (
+                    (*cmsg).cmsg_level,
+                    (*cmsg).cmsg_type,
+                    (*cmsg).cmsg_len as usize,
+                )
+            };
+            if level == SOL_SOCKET && kind == SCM_RIGHTS {
+                let data = unsafe { CMSG_DATA(cmsg) as *const c_int };
+                let count =
+                    len.saturating_sub(unsafe { CMSG_LEN(0) } as usize) / mem::size_of::<c_int>();
+                for i in 0..count {
+                    fds.push(unsafe { ptr::read_unaligned(data.add(i)) });
+                }
+            }
+            cmsg = unsafe { CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        let truncated = msg.msg_flags & MSG_CTRUNC != 0;
+        let mut fds = fds.into_iter();
+        let fd = if truncated { None } else { fds.next() };
+        for extra in fds {
+            unsafe { close(extra) };
+        }
+
+        // descriptors that did not fit in the control buffer were closed by the kernel
+        if truncated {
+            return Err(SocketError::InvalidInput(
+                "Message carried more file descriptors than fit",
+            ));
+        }
+        let fd = fd.ok_or(SocketError::InvalidInput(
+            "Message did not carry a file descriptor",
+        ))?;
+
+        #[cfg(not(target_os = "linux"))]
+        if unsafe { fcntl(fd, F_SETFD, FD_CLOEXEC) } == -1 {
+            let err = SocketError::last_os_error();
+            unsafe { close(fd) };
+            return Err(err);
+        }
+
+        Ok(fd)
+    }
+
+    // lets a server over a Unix domain socket decide what the connecting user may do
+    pub fn peer_credentials(&self) -> Result<PeerCred> {
+        if self.family != AF_UNIX {
+            return Err(SocketError::InvalidInput(
+                "Socket is not a Unix domain socket",
+            ));
+        }
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        self.peer_cred()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn peer_cred(&self) -> Result<PeerCred> {
+        let cred: libc::ucred = self.get_option(SOL_SOCKET, libc::SO_PEERCRED)?;
+
+        Ok(PeerCred {
+            pid: cred.pid,
+            uid: cred.uid,
+            gid: cred.gid,
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn peer_cred(&self) -> Result<PeerCred> {
+        let mut uid = 0;
+        let mut gid = 0;
+        let res = unsafe { getpeereid(self.fd, &mut uid, &mut gid) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        let pid = self.get_int_option(libc::SOL_LOCAL, libc::LOCAL_PEERPID)?;
+
+        Ok(PeerCred { pid, uid, gid })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn peer_cred(&self) -> Result<PeerCred> {
+        Err(SocketError::Unsupported(
+            "Peer credentials are not supported on this platform",
+        ))
+    }
+
+    // fills buf with received data, a stream socket has three outcomes:
+    // - Ok(n) with n > 0: n bytes were read
+    // - Ok(0): the peer closed the connection, every later recv returns Ok(0) as well, this is
+    //   never reported as an error (an empty buf also gives Ok(0), so pass a non-empty one)
+    // - Err(WouldBlock): a non-blocking socket has nothing queued yet but is still open, a
+    //   blocking socket whose read timeout fired reports TimedOut instead
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_with_flags(buf, 0)
+    }
+
+    // like recv but leaves the data in the receive queue, so the next recv returns it again
+    pub fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_with_flags(buf, MSG_PEEK)
+    }
+
+    // like recv_exact but in a single syscall, the kernel holds the call until buf is full,
+    // a short count still comes back when the peer closes, a timeout fires or a signal
+    // interrupts the wait, so check the returned length
+    pub fn recv_waitall(&self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_with_flags(buf, MSG_WAITALL)
+    }
+
+    // reads the pending urgent byte, this never waits for one: it fails with WouldBlock (or
+    // TimedOut on a blocking socket) while the byte is still in flight and with EINVAL when
+    // there is none or SO_OOBINLINE is set
+    pub fn recv_oob(&self) -> Result<u8> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "Out-of-band data only applies to stream sockets",
+            ));
+        }
+
+        let mut byte = [0u8; 1];
+        match self.recv_with_flags(&mut byte, MSG_OOB)? {
+            0 => Err(SocketError::UnexpectedEof),
+            _ => Ok(byte[0]),
+        }
+    }
+
+    // number of bytes waiting in the receive queue, a recv of that size won't block, for a
+    // datagram socket this is the size of the next datagram on Linux but the total on macOS
+    pub fn available(&self) -> Result<usize> {
+        let mut count: c_int = 0;
+        let res = unsafe { ioctl(self.fd, FIONREAD, &mut count) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(count as usize)
+    }
+
+    fn recv_with_flags(&self, buf: &mut [u8], flags: i32) -> Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let res = unsafe { recv(self.fd, buf.as_mut_ptr(), buf.len(), flags) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        // peeked data is still queued and gets counted when it is actually read
+        if flags & MSG_PEEK == 0 {
+            Counters::add(&self.counters.received, res as usize);
+        }
+        Ok(res as usize)
+    }
+
+    // sends a single datagram, returns the number of bytes sent
+    pub fn send_to(&self, buf: &[u8], addr: impl ToEndpoint) -> Result<usize> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+        let addr = self.sockaddr(&addr)?;
+
+        let res = unsafe {
+            sendto(
+                self.fd,
+                buf.as_ptr(),
+                buf.len(),
+                0,
+                addr.as_ptr(),
+                addr.size(),
+            )
+        };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Counters::add(&self.counters.sent, res as usize);
+        Ok(res as usize)
+    }
+
+    // receives a single datagram, returns the bytes read and who sent it
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Endpoint)> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+
+        let res = unsafe {
+            recvfrom(
+                self.fd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                0,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        Counters::add(&self.counters.received, res as usize);
+        Ok((res as usize, endpoint_from_storage(&addr, addr_len)?))
+    }
+
+    // like recv_from but also returns the local address the datagram was sent to, which a
+    // server bound to 0.0.0.0 needs to reply from the right source IP, it is None unless
+    // set_recv_dst_addr is on
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn recv_from_with_info(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<(usize, Endpoint, Option<Ipv4Addr>)> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut iov = iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        };
+        let mut control = [0u64; PKTINFO_CONTROL_WORDS];
+
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut addr as *mut sockaddr_storage as *mut c_void;
+        msg.msg_namelen = mem::size_of::<sockaddr_storage>() as socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let res = unsafe { recvmsg(self.fd, &mut msg, 0) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+        Counters::add(&self.counters.received, res as usize);
+
+        let mut dst = None;
+        let mut cmsg = unsafe { CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let (level, kind) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+            if level == IPPROTO_IP && kind == libc::IP_PKTINFO {
+                let info =
+                    unsafe { ptr::read_unaligned(CMSG_DATA(cmsg) as *const libc::in_pktinfo) };
+                dst = Some(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)));
+            }
+            cmsg = unsafe { CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        let src = endpoint_from_storage(&addr, msg.msg_namelen)?;
+        Ok((res as usize, src, dst))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn recv_from_with_info(
+        &self,
+        _buf: &mut [u8],
+    ) -> Result<(usize, Endpoint, Option<Ipv4Addr>)> {
+        Err(SocketError::Unsupported(
+            "IP_PKTINFO is not supported on this platform",
+        ))
+    }
+
+    // receives up to bufs.len() datagrams (at most MMSG_BATCH) in one syscall, blocking only
+    // until the first arrives, returns the bytes read and the sender of each datagram
+    #[cfg(target_os = "linux")]
+    pub fn recv_mmsg(&self, bufs: &mut [&mut [u8]]) -> Result<Vec<(usize, Endpoint)>> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+
+        let count = bufs.len().min(MMSG_BATCH);
+        let mut addrs: Vec<sockaddr_storage> = vec![unsafe { mem::zeroed() }; count];
+        let mut iovs: Vec<iovec> = bufs
+            .iter_mut()
+            .take(count)
+            .map(|buf| iovec {
+                iov_base: buf.as_mut_ptr() as *mut c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let mut msgs: Vec<libc::mmsghdr> = iovs
+            .iter_mut()
+            .zip(addrs.iter_mut())
+            .map(|(iov, addr)| {
+                let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                msg.msg_hdr.msg_name = addr as *mut sockaddr_storage as *mut c_void;
+                msg.msg_hdr.msg_namelen = mem::size_of::<sockaddr_storage>() as socklen_t;
+                msg.msg_hdr.msg_iov = iov;
+                msg.msg_hdr.msg_iovlen = 1;
+                msg
+            })
+            .collect();
+
+        let res = unsafe {
+            recvmmsg(
+                self.fd,
+                msgs.as_mut_ptr(),
+                msgs.len() as c_uint,
+                libc::MSG_WAITFORONE,
+                ptr::null_mut(),
+            )
+        };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        msgs[..res as usize]
+            .iter()
+            .zip(addrs.iter())
+            .map(|(msg, addr)| {
+                Counters::add(&self.counters.received, msg.msg_len as usize);
+                let endpoint = endpoint_from_storage(addr, msg.msg_hdr.msg_namelen)?;
+                Ok((msg.msg_len as usize, endpoint))
+            })
+            .collect()
+    }
+
+    // without recvmmsg this receives a single datagram per call
+    #[cfg(not(target_os = "linux"))]
+    pub fn recv_mmsg(&self, bufs: &mut [&mut [u8]]) -> Result<Vec<(usize, Endpoint)>> {
+        match bufs.first_mut() {
+            Some(buf) => Ok(vec![self.recv_from(buf)?]),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // sends each (payload, addr) as its own datagram, at most MMSG_BATCH per call, and
+    // returns how many were sent, which can be less than msgs.len(), resend the rest
+    #[cfg(target_os = "linux")]
+    pub fn send_mmsg<A: ToEndpoint>(&self, msgs: &[(&[u8], A)]) -> Result<usize> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+
+        let count = msgs.len().min(MMSG_BATCH);
+        let addrs = msgs[..count]
+            .iter()
+            .map(|(_, addr)| self.sockaddr(addr))
+            .collect::<Result<Vec<_>>>()?;
+        let mut iovs: Vec<iovec> = msgs[..count]
+            .iter()
+            .map(|(buf, _)| iovec {
+                iov_base: buf.as_ptr() as *mut c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let mut hdrs: Vec<libc::mmsghdr> = iovs
+            .iter_mut()
+            .zip(addrs.iter())
+            .map(|(iov, addr)| {
+                let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                msg.msg_hdr.msg_name = addr.as_ptr() as *mut c_void;
+                msg.msg_hdr.msg_namelen = addr.size();
+                msg.msg_hdr.msg_iov = iov;
+                msg.msg_hdr.msg_iovlen = 1;
+                msg
+            })
+            .collect();
+
+        let res = unsafe { sendmmsg(self.fd, hdrs.as_mut_ptr(), hdrs.len() as c_uint, 0) };
+
+        if res == -1 {
+            return Err(self.last_error());
+        }
+
+        for hdr in &hdrs[..res as usize] {
+            Counters::add(&self.counters.sent, hdr.msg_len as usize);
+        }
+
+        Ok(res as usize)
+    }
+
+    // without sendmmsg this falls back to one sendto per datagram
+    #[cfg(not(target_os = "linux"))]
+    pub fn send_mmsg<A: ToEndpoint>(&self, msgs: &[(&[u8], A)]) -> Result<usize> {
+        for (sent, (buf, addr)) in msgs.iter().take(MMSG_BATCH).enumerate() {
+            if let Err(err) = self.send_to(buf, addr) {
+                // report what went out so far, the error resurfaces on the next call
+                return if sent == 0 { Err(err) } else { Ok(sent) };
+            }
+        }
+        Ok(msgs.len().min(MMSG_BATCH))
+    }
+
+    // lets bind reuse a local address still in TIME_WAIT, must be set before bind
+    pub fn set_reuse_address(&self, enable: bool) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, SO_REUSEADDR, enable as c_int)
+    }
+
+    // lets several sockets bind the same address and port, must be set before bind
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    pub fn set_reuse_port(&self, enable: bool) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, libc::SO_REUSEPORT, enable as c_int)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+    pub fn set_reuse_port(&self, _enable: bool) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "SO_REUSEPORT is not supported on this platform",
+        ))
+    }
+
+    // delivers urgent data in the normal stream instead of through recv_oob
+    pub fn set_oob_inline(&self, enable: bool) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, SO_OOBINLINE, enable as c_int)
+    }
+
+    pub fn oob_inline(&self) -> Result<bool> {
+        Ok(self.get_int_option(SOL_SOCKET, SO_OOBINLINE)? != 0)
+    }
+
+    // disables Nagle's algorithm so small writes go out immediately
+    pub fn set_nodelay(&self, enable: bool) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_NODELAY only applies to stream sockets",
+            ));
+        }
+        self.set_int_option(IPPROTO_TCP, TCP_NODELAY, enable as c_int)
+    }
+
+    pub fn nodelay(&self) -> Result<bool> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_NODELAY only applies to stream sockets",
+            ));
+        }
+        Ok(self.get_int_option(IPPROTO_TCP, TCP_NODELAY)? != 0)
+    }
+
+    // holds back partial segments until the returned guard is dropped, so several small
+    // sends (e.g. a header and a body) leave as full segments instead of one packet each
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn cork(&self) -> Result<Cork<'_>> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "Corking only applies to stream sockets",
+            ));
+        }
+        self.set_int_option(IPPROTO_TCP, CORK_OPTION, 1)?;
+        Ok(Cork { socket: self })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn cork(&self) -> Result<Cork<'_>> {
+        Err(SocketError::Unsupported(
+            "Corking is not supported on this platform",
+        ))
+    }
+
+    // None blocks forever, otherwise recv returns TimedOut once dur passes without data
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        self.set_option(SOL_SOCKET, SO_RCVTIMEO, &duration_to_timeval(dur)?)
+    }
+
+    // None blocks forever, otherwise send returns TimedOut once dur passes without progress
+    pub fn set_write_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        self.set_option(SOL_SOCKET, SO_SNDTIMEO, &duration_to_timeval(dur)?)
+    }
+
+    // controls what close (and Drop) does with unsent data:
+    // None returns immediately and lets the kernel flush in the background (the default),
+    // Some(Duration::ZERO) discards unsent data and resets the connection with an RST,
+    // Some(dur) makes close block for up to dur while the data is sent, rounded up to whole
+    // seconds
+    pub fn set_linger(&self, linger: Option<Duration>) -> Result<()> {
+        let value = libc::linger {
+            l_onoff: linger.is_some() as c_int,
+            l_linger: linger.map_or(Ok(0), linger_secs)?,
+        };
+        self.set_option(SOL_SOCKET, SO_LINGER, &value)
+    }
+
+    pub fn linger(&self) -> Result<Option<Duration>> {
+        let value: libc::linger = self.get_option(SOL_SOCKET, SO_LINGER)?;
+
+        if value.l_onoff == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_secs(value.l_linger as u64)))
+        }
+    }
+
+    // None turns keepalive off, Some probes the peer once the connection has been idle long
+    // enough and drops it if the probes go unanswered
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn set_keepalive(&self, params: Option<KeepaliveParams>) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "Keepalive only applies to stream sockets",
+            ));
+        }
+
+        let Some(params) = params else {
+            return self.set_int_option(SOL_SOCKET, SO_KEEPALIVE, 0);
+        };
+
+        // validate everything before touching the socket so a bad value changes nothing
+        let idle = keepalive_secs(params.idle)?;
+        let interval = keepalive_secs(params.interval)?;
+        let retries = c_int::try_from(params.retries)
+            .ok()
+            .filter(|&retries| retries > 0)
+            .ok_or(SocketError::InvalidInput(
+                "Keepalive retries must be between 1 and i32::MAX",
+            ))?;
+
+        self.set_int_option(IPPROTO_TCP, KEEPIDLE_OPTION, idle)?;
+        self.set_int_option(IPPROTO_TCP, TCP_KEEPINTVL, interval)?;
+        self.set_int_option(IPPROTO_TCP, TCP_KEEPCNT, retries)?;
+        self.set_int_option(SOL_SOCKET, SO_KEEPALIVE, 1)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn set_keepalive(&self, params: Option<KeepaliveParams>) -> Result<()> {
+        match params {
+            None => self.set_int_option(SOL_SOCKET, SO_KEEPALIVE, 0),
+            Some(_) => Err(SocketError::Unsupported(
+                "Keepalive tuning is not supported on this platform",
+            )),
+        }
+    }
+
+    // None when keepalive is off, otherwise the probe settings currently in effect
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn keepalive(&self) -> Result<Option<KeepaliveParams>> {
+        if self.get_int_option(SOL_SOCKET, SO_KEEPALIVE)? == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(KeepaliveParams {
+            idle: Duration::from_secs(self.get_int_option(IPPROTO_TCP, KEEPIDLE_OPTION)? as u64),
+            interval: Duration::from_secs(self.get_int_option(IPPROTO_TCP, TCP_KEEPINTVL)? as u64),
+            retries: self.get_int_option(IPPROTO_TCP, TCP_KEEPCNT)? as u32,
+        }))
+    }
+
+    // fails the connection once sent data has gone unacknowledged for dur, instead of
+    // retransmitting for the default ~15 minutes, Duration::ZERO restores the default
+    #[cfg(target_os = "linux")]
+    pub fn set_user_timeout(&self, dur: Duration) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_USER_TIMEOUT only applies to stream sockets",
+            ));
+        }
+        let millis = c_int::try_from(dur.as_millis())
+            .map_err(|_| SocketError::InvalidInput("User timeout is too large"))?;
+        self.set_int_option(IPPROTO_TCP, libc::TCP_USER_TIMEOUT, millis)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_user_timeout(&self, _dur: Duration) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "TCP_USER_TIMEOUT is only supported on Linux",
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn user_timeout(&self) -> Result<Duration> {
+        let millis = self.get_int_option(IPPROTO_TCP, libc::TCP_USER_TIMEOUT)?;
+        Ok(Duration::from_millis(millis as u64))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn user_timeout(&self) -> Result<Duration> {
+        Err(SocketError::Unsupported(
+            "TCP_USER_TIMEOUT is only supported on Linux",
+        ))
+    }
+
+    // a listener only hands out a connection once the client has sent data, or the timeout
+    // in seconds passed, the kernel rounds it up to its retransmission schedule so
+    // defer_accept can report more, 0 turns it off
+    #[cfg(target_os = "linux")]
+    pub fn set_defer_accept(&self, seconds: u32) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_DEFER_ACCEPT only applies to stream sockets",
+            ));
+        }
+        let seconds = c_int::try_from(seconds)
+            .map_err(|_| SocketError::InvalidInput("Defer accept timeout is too large"))?;
+        self.set_int_option(IPPROTO_TCP, libc::TCP_DEFER_ACCEPT, seconds)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_defer_accept(&self, _seconds: u32) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "TCP_DEFER_ACCEPT is only supported on Linux",
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn defer_accept(&self) -> Result<u32> {
+        Ok(self.get_int_option(IPPROTO_TCP, libc::TCP_DEFER_ACCEPT)? as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn defer_accept(&self) -> Result<u32> {
+        Err(SocketError::Unsupported(
+            "TCP_DEFER_ACCEPT is only supported on Linux",
+        ))
+    }
+
+    // rtt, retransmissions and congestion window as the kernel sees them right now, useful to
+    // spot a degraded connection
+    #[cfg(target_os = "linux")]
+    pub fn tcp_info(&self) -> Result<TcpInfo> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_INFO only applies to stream sockets",
+            ));
+        }
+
+        let info: libc::tcp_info = self.get_option(IPPROTO_TCP, libc::TCP_INFO)?;
+
+        // the kernel reports times in microseconds
+        Ok(TcpInfo {
+            state: info.tcpi_state,
+            rtt: Duration::from_micros(info.tcpi_rtt as u64),
+            rtt_var: Duration::from_micros(info.tcpi_rttvar as u64),
+            retransmits: info.tcpi_retransmits,
+            total_retransmits: info.tcpi_total_retrans,
+            snd_cwnd: info.tcpi_snd_cwnd,
+            snd_mss: info.tcpi_snd_mss,
+            unacked: info.tcpi_unacked,
+            lost: info.tcpi_lost,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn tcp_info(&self) -> Result<TcpInfo> {
+        Err(SocketError::Unsupported(
+            "TCP_INFO is only supported on Linux",
+        ))
+    }
+
+    // picks the congestion control algorithm, e.g. "bbr" or "cubic", it has to be built in or
+    // loaded as a module, and unprivileged processes are limited to the ones listed in
+    // net.ipv4.tcp_allowed_congestion_control
+    #[cfg(target_os = "linux")]
+    pub fn set_congestion(&self, algo: &str) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_CONGESTION only applies to stream sockets",
+            ));
+        }
+        if algo.len() >= TCP_CA_NAME_MAX {
+            return Err(SocketError::InvalidInput(
+                "Congestion control algorithm name is too long",
+            ));
+        }
+        if algo.contains('\0') {
+            return Err(SocketError::InvalidInput(
+                "Congestion control algorithm name contains a null byte",
+            ));
+        }
+
+        match self.set_option_raw(IPPROTO_TCP, libc::TCP_CONGESTION, algo.as_bytes()) {
+            Err(SocketError::Os(libc::ENOENT)) => Err(SocketError::InvalidInput(
+                "Congestion control algorithm is not available",
+            )),
+            res => res,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_congestion(&self, _algo: &str) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "TCP_CONGESTION is only supported on Linux",
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn congestion(&self) -> Result<String> {
+        let mut name = [0u8; TCP_CA_NAME_MAX];
+        let len = self.get_option_raw(IPPROTO_TCP, libc::TCP_CONGESTION, &mut name)?;
+
+        // the kernel fills the whole buffer, padded with null bytes
+        let name = &name[..len];
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Ok(String::from_utf8_lossy(&name[..end]).into_owned())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn congestion(&self) -> Result<String> {
+        Err(SocketError::Unsupported(
+            "TCP_CONGESTION is only supported on Linux",
+        ))
+    }
+
+    // lets a listener accept data carried in the SYN of a TCP Fast Open connect, queue_len
+    // bounds how many such connections can wait for accept, 0 turns it off, macOS only has
+    // an on/off switch and ignores the length
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn set_fastopen(&self, queue_len: u32) -> Result<()> {
+        if self.sock_type != SOCK_STREAM {
+            return Err(SocketError::InvalidInput(
+                "TCP_FASTOPEN only applies to stream sockets",
+            ));
+        }
+        let queue_len = c_int::try_from(queue_len)
+            .map_err(|_| SocketError::InvalidInput("Fast Open queue length is too large"))?;
+        #[cfg(target_os = "macos")]
+        let queue_len = (queue_len > 0) as c_int;
+        self.set_int_option(IPPROTO_TCP, libc::TCP_FASTOPEN, queue_len)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn set_fastopen(&self, _queue_len: u32) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "TCP_FASTOPEN is not supported on this platform",
+        ))
+    }
+
+    // the queue length on Linux, 1 or 0 on macOS
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn fastopen_queue_len(&self) -> Result<u32> {
+        Ok(self.get_int_option(IPPROTO_TCP, libc::TCP_FASTOPEN)? as u32)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn fastopen_queue_len(&self) -> Result<u32> {
+        Err(SocketError::Unsupported(
+            "TCP_FASTOPEN is not supported on this platform",
+        ))
+    }
+
+    // the kernel may double or clamp the requested size, so send_buffer_size can report a
+    // different value than was set
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, SO_SNDBUF, buffer_size_to_int(size)?)
+    }
+
+    pub fn send_buffer_size(&self) -> Result<usize> {
+        Ok(self.get_int_option(SOL_SOCKET, SO_SNDBUF)? as usize)
+    }
+
+    // same caveat as set_send_buffer_size
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, SO_RCVBUF, buffer_size_to_int(size)?)
+    }
+
+    pub fn recv_buffer_size(&self) -> Result<usize> {
+        Ok(self.get_int_option(SOL_SOCKET, SO_RCVBUF)? as usize)
+    }
+
+    // poll and select only report the socket readable once at least bytes are queued, which
+    // saves wakeups for fixed size messages, recv still returns whatever is there, a closed
+    // connection or a pending error wakes up regardless, 0 is treated as 1
+    pub fn set_recv_lowat(&self, bytes: usize) -> Result<()> {
+        let bytes = c_int::try_from(bytes)
+            .map_err(|_| SocketError::InvalidInput("Low-water mark is too large"))?;
+        self.set_int_option(SOL_SOCKET, SO_RCVLOWAT, bytes)
+    }
+
+    pub fn recv_lowat(&self) -> Result<usize> {
+        Ok(self.get_int_option(SOL_SOCKET, SO_RCVLOWAT)? as usize)
+    }
+
+    // escape hatch for options without a dedicated method, value is passed to the kernel as is
+    pub fn set_option_raw(&self, level: i32, name: i32, value: &[u8]) -> Result<()> {
+        let len = socklen_t::try_from(value.len())
+            .map_err(|_| SocketError::InvalidInput("Option value is too large"))?;
+
+        let res = unsafe { setsockopt(self.fd, level, name, value.as_ptr() as *const c_void, len) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // returns how many bytes of buf the kernel filled in
+    pub fn get_option_raw(&self, level: i32, name: i32, buf: &mut [u8]) -> Result<usize> {
+        // a shorter length than buf is harmless, the kernel never writes past it
+        let mut len = socklen_t::try_from(buf.len()).unwrap_or(socklen_t::MAX);
+
+        let res = unsafe {
+            getsockopt(
+                self.fd,
+                level,
+                name,
+                buf.as_mut_ptr() as *mut c_void,
+                &mut len,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        // some platforms report the option's full size when buf was too small for it
+        Ok((len as usize).min(buf.len()))
+    }
+
+    // allows send_to onto a broadcast address such as 255.255.255.255, which otherwise
+    // fails with EACCES
+    pub fn set_broadcast(&self, enable: bool) -> Result<()> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+        self.set_int_option(SOL_SOCKET, SO_BROADCAST, enable as c_int)
+    }
+
+    pub fn broadcast(&self) -> Result<bool> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+        Ok(self.get_int_option(SOL_SOCKET, SO_BROADCAST)? != 0)
+    }
+
+    // time-to-live of outgoing IPv4 packets, each router hop decrements it by one
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        self.set_int_option(IPPROTO_IP, IP_TTL, ttl_to_int(ttl)?)
+    }
+
+    pub fn ttl(&self) -> Result<u32> {
+        Ok(self.get_int_option(IPPROTO_IP, IP_TTL)? as u32)
+    }
+
+    // the type-of-service byte of outgoing IPv4 packets, the upper six bits are the DSCP
+    // class (e.g. 46 << 2 for expedited forwarding), the lower two carry ECN
+    pub fn set_tos(&self, tos: u8) -> Result<()> {
+        self.set_int_option(IPPROTO_IP, IP_TOS, tos as c_int)
+    }
+
+    pub fn tos(&self) -> Result<u8> {
+        Ok(self.get_int_option(IPPROTO_IP, IP_TOS)? as u8)
+    }
+
+    // with only_v6 off an IPv6 socket also serves IPv4 peers through IPv4-mapped addresses
+    // (::ffff:a.b.c.d), the default differs between systems and sysctls, so set it explicitly
+    // before bind when it matters
+    pub fn set_only_v6(&self, only: bool) -> Result<()> {
+        if self.family != AF_INET6 {
+            return Err(SocketError::InvalidInput("Socket is not an IPv6 socket"));
+        }
+        self.set_int_option(IPPROTO_IPV6, IPV6_V6ONLY, only as c_int)
+    }
+
+    pub fn only_v6(&self) -> Result<bool> {
+        if self.family != AF_INET6 {
+            return Err(SocketError::InvalidInput("Socket is not an IPv6 socket"));
+        }
+        Ok(self.get_int_option(IPPROTO_IPV6, IPV6_V6ONLY)? != 0)
+    }
+
+    // restricts the socket to one network interface, e.g. "eth0", regardless of routing, an
+    // empty name removes the restriction, usually needs CAP_NET_RAW and reports
+    // PermissionDenied without it
+    #[cfg(target_os = "linux")]
+    pub fn bind_to_device(&self, interface: &str) -> Result<()> {
+        if interface.len() >= libc::IFNAMSIZ {
+            return Err(SocketError::InvalidInput("Interface name is too long"));
+        }
+        if interface.contains('\0') {
+            return Err(SocketError::InvalidInput(
+                "Interface name contains a null byte",
+            ));
+        }
+        self.set_option_raw(SOL_SOCKET, libc::SO_BINDTODEVICE, interface.as_bytes())
+    }
+
+    // tags every packet the socket sends with mark, for iptables rules or "ip rule fwmark"
+    // policy routing, needs CAP_NET_ADMIN and reports PermissionDenied without it
+    #[cfg(target_os = "linux")]
+    pub fn set_mark(&self, mark: u32) -> Result<()> {
+        self.set_int_option(SOL_SOCKET, libc::SO_MARK, mark as c_int)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn mark(&self) -> Result<u32> {
+        Ok(self.get_int_option(SOL_SOCKET, libc::SO_MARK)? as u32)
+    }
+
+    // joins an IPv4 multicast group on the given local interface, UNSPECIFIED lets the
+    // kernel pick one, the socket should be bound to the group's port
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.set_multicast_membership(IP_ADD_MEMBERSHIP, group, interface)
+    }
+
+    pub fn leave_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.set_multicast_membership(IP_DROP_MEMBERSHIP, group, interface)
+    }
+
+    // how many hops outgoing multicast packets may travel, 1 (the default) keeps them on
+    // the local network
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> Result<()> {
+        self.set_int_option(IPPROTO_IP, IP_MULTICAST_TTL, ttl_to_int(ttl)?)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> Result<u32> {
+        Ok(self.get_int_option(IPPROTO_IP, IP_MULTICAST_TTL)? as u32)
+    }
+
+    // whether multicast packets sent from this host are also delivered back to it
+    pub fn set_multicast_loop_v4(&self, enable: bool) -> Result<()> {
+        self.set_int_option(IPPROTO_IP, IP_MULTICAST_LOOP, enable as c_int)
+    }
+
+    pub fn multicast_loop_v4(&self) -> Result<bool> {
+        Ok(self.get_int_option(IPPROTO_IP, IP_MULTICAST_LOOP)? != 0)
+    }
+
+    // controls path MTU discovery, with MtuDiscover::Do a UDP app learns the largest datagram
+    // that gets through unfragmented from path_mtu or an EMSGSIZE error
+    #[cfg(target_os = "linux")]
+    pub fn set_mtu_discover(&self, mode: MtuDiscover) -> Result<()> {
+        self.set_int_option(IPPROTO_IP, libc::IP_MTU_DISCOVER, mode.as_raw())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_mtu_discover(&self, _mode: MtuDiscover) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "IP_MTU_DISCOVER is only supported on Linux",
+        ))
+    }
+
+    // the path MTU the kernel currently knows for the connected peer, fails with ENOTCONN
+    // on an unconnected socket
+    #[cfg(target_os = "linux")]
+    pub fn path_mtu(&self) -> Result<u32> {
+        Ok(self.get_int_option(IPPROTO_IP, libc::IP_MTU)? as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn path_mtu(&self) -> Result<u32> {
+        Err(SocketError::Unsupported(
+            "IP_MTU is only supported on Linux",
+        ))
+    }
+
+    // makes the kernel attach the destination address of each IPv4 datagram, read it back
+    // with recv_from_with_info
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn set_recv_dst_addr(&self, enable: bool) -> Result<()> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+        self.set_int_option(IPPROTO_IP, libc::IP_PKTINFO, enable as c_int)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn set_recv_dst_addr(&self, _enable: bool) -> Result<()> {
+        Err(SocketError::Unsupported(
+            "IP_PKTINFO is not supported on this platform",
+        ))
+    }
+
+    // picks the local interface, by its address, that outgoing multicast leaves through on a
+    // multi-homed host, UNSPECIFIED goes back to following the routing table
+    pub fn set_multicast_interface_v4(&self, interface: Ipv4Addr) -> Result<()> {
+        let addr = in_addr {
+            s_addr: u32::from(interface).to_be(),
+        };
+        self.set_option(IPPROTO_IP, IP_MULTICAST_IF, &addr)
+    }
+
+    pub fn multicast_interface_v4(&self) -> Result<Ipv4Addr> {
+        let addr: in_addr = self.get_option(IPPROTO_IP, IP_MULTICAST_IF)?;
+        Ok(Ipv4Addr::from(u32::from_be(addr.s_addr)))
+    }
+
+    fn set_multicast_membership(
+        &self,
+        name: i32,
+        group: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<()> {
+        if self.sock_type != SOCK_DGRAM {
+            return Err(SocketError::InvalidInput("Socket is not a datagram socket"));
+        }
+
+        let mreq = ip_mreq {
+            imr_multiaddr: in_addr {
+                s_addr: u32::from(group).to_be(),
+            },
+            imr_interface: in_addr {
+                s_addr: u32::from(interface).to_be(),
+            },
+        };
+        self.set_option(IPPROTO_IP, name, &mreq)
+    }
+
+    fn set_int_option(&self, level: i32, name: i32, value: c_int) -> Result<()> {
+        self.set_option(level, name, &value)
+    }
+
+    fn set_option<T>(&self, level: i32, name: i32, value: &T) -> Result<()> {
+        let res = unsafe {
+            setsockopt(
+                self.fd,
+                level,
+                name,
+                value as *const T as *const c_void,
+                mem::size_of::<T>() as socklen_t,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn get_int_option(&self, level: i32, name: i32) -> Result<c_int> {
+        self.get_option(level, name)
+    }
+
+    // T must be a plain C type (int, struct linger, ...) for which all zero bytes are valid
+    fn get_option<T: Copy>(&self, level: i32, name: i32) -> Result<T> {
+        let mut value: T = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<T>() as socklen_t;
+
+        let res = unsafe {
+            getsockopt(
+                self.fd,
+                level,
+                name,
+                &mut value as *mut T as *mut c_void,
+                &mut len,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(value)
+    }
+
+    // a blocking socket only sees EAGAIN/EWOULDBLOCK when one of its timeouts fired
+    fn last_error(&self) -> SocketError {
+        let err = SocketError::last_os_error();
+        if err != SocketError::WouldBlock {
+            return err;
+        }
+
+        match self.nonblocking() {
+            Ok(false) => SocketError::TimedOut,
+            _ => SocketError::WouldBlock,
+        }
+    }
+
+    // moves the socket onto target_fd, e.g. 0 and 1 in a forked child so the program it execs
+    // talks to the peer through stdin and stdout, inetd style, whatever target_fd referred to
+    // is closed first, the socket's own descriptor is closed afterwards so target_fd is left
+    // as the only one, without close-on-exec, and nothing closes it on Drop
+    pub fn redirect_to(self, target_fd: RawFd) -> Result<()> {
+        if self.state == SocketState::Closed {
+            return Err(SocketError::InvalidState("Socket is closed"));
+        }
+
+        // dup2 onto itself is a no-op that would keep close-on-exec set, ownership is only
+        // given up once that worked so Drop still closes the socket on failure
+        if self.fd == target_fd {
+            if unsafe { fcntl(self.fd, F_SETFD, 0) } == -1 {
+                return Err(SocketError::last_os_error());
+            }
+            let _ = self.into_raw_fd();
+            return Ok(());
+        }
+
+        let fd = self.into_raw_fd();
+        let res = unsafe { dup2(fd, target_fd) };
+        let err = SocketError::last_os_error();
+        unsafe { close(fd) };
+
+        if res == -1 {
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    // hands the descriptor over to std, e.g. for a library that wants a TcpStream, options
+    // like timeouts and non-blocking mode carry over since they live in the kernel
+    pub fn into_tcp_stream(self) -> Result<TcpStream> {
+        if self.sock_type != SOCK_STREAM || (self.family != AF_INET && self.family != AF_INET6) {
+            return Err(SocketError::InvalidInput("Socket is not a TCP socket"));
+        }
+        if self.state == SocketState::Closed {
+            return Err(SocketError::InvalidState("Socket is closed"));
+        }
+
+        Ok(unsafe { TcpStream::from_raw_fd(self.into_raw_fd()) })
+    }
+
+    // the other way round, family, type and state are read back from the kernel like
+    // from_raw_fd does
+    pub fn from_tcp_stream(stream: TcpStream) -> Socket {
+        unsafe { Socket::from_raw_fd(stream.into_raw_fd()) }
+    }
+
+    // duplicates the descriptor, both sockets refer to the same connection but can be closed
+    // independently, the connection itself stays open until the last of them is closed
+    pub fn try_clone(&self) -> Result<Socket> {
+        let fd = unsafe { fcntl(self.fd, F_DUPFD_CLOEXEC, 0) };
+
+        if fd == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(Socket {
+            fd,
+            state: self.state.clone(),
+            family: self.family,
+            sock_type: self.sock_type,
+            counters: Counters::default(),
+            peer: self.peer.clone(),
+            max_frame_len: self.max_frame_len,
+            wakeup: self.wakeup.clone(),
+        })
+    }
+
+    // close-on-exec is on by default, turning it off lets a child process inherit the socket
+    pub fn set_cloexec(&self, enable: bool) -> Result<()> {
+        let flags = unsafe { fcntl(self.fd, F_GETFD) };
+
+        if flags == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        let flags = if enable {
+            flags | FD_CLOEXEC
+        } else {
+            flags & !FD_CLOEXEC
+        };
+
+        let res = unsafe { fcntl(self.fd, F_SETFD, flags) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // in non-blocking mode accept/connect/send/recv return WouldBlock instead of waiting
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        let flags = unsafe { fcntl(self.fd, F_GETFL) };
+
+        if flags == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        let flags = if nonblocking {
+            flags | O_NONBLOCK
+        } else {
+            flags & !O_NONBLOCK
+        };
+
+        let res = unsafe { fcntl(self.fd, F_SETFL, flags) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // reads the mode back from the descriptor rather than remembering what set_nonblocking
+    // was given, the flag is shared with every try_clone and with whoever handed the fd to
+    // from_raw_fd, so any of them may have changed it
+    pub fn nonblocking(&self) -> Result<bool> {
+        let flags = unsafe { fcntl(self.fd, F_GETFL) };
+
+        if flags == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(flags & O_NONBLOCK != 0)
+    }
+
+    // waits until the socket has data to read, or for a listener a connection to accept,
+    // None waits forever, returns false if the timeout elapsed first
+    pub fn poll_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.poll_events(POLLIN, timeout)
+    }
+
+    // waits until the socket can accept more outgoing data without blocking
+    pub fn poll_writable(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.poll_events(POLLOUT, timeout)
+    }
+
+    fn poll_events(&self, events: c_short, timeout: Option<Duration>) -> Result<bool> {
+        let deadline = timeout.map(|dur| Instant::now() + dur);
+
+        loop {
+            let mut fds = pollfd {
+                fd: self.fd,
+                events,
+                revents: 0,
+            };
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    poll_timeout_ms(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => -1,
+            };
+            let res = unsafe { poll(&mut fds, 1, timeout_ms) };
+
+            match res {
+                0 => return Ok(false),
+                -1 => {
+                    // a signal interrupted the wait, keep waiting for whatever time is left
+                    if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(SocketError::last_os_error());
+                }
+                // errors and hangups count as ready too, the next call on the socket reports them
+                _ => return Ok(true),
+            }
+        }
+    }
+
+    // asks the kernel rather than trusting what the socket was created as, so it also holds
+    // for descriptors adopted through FromRawFd
+    pub fn socket_type(&self) -> Result<i32> {
+        self.get_int_option(SOL_SOCKET, SO_TYPE)
+    }
+
+    // the kernel's view of whether listen was called, unlike is_listening this doesn't rely
+    // on the tracked state, which only guesses for descriptors of unknown history
+    pub fn is_listening_socket(&self) -> Result<bool> {
+        Ok(self.get_int_option(SOL_SOCKET, SO_ACCEPTCONN)? != 0)
+    }
+
+    // the protocol the socket was created with, e.g. IPPROTO_TCP or IPPROTO_UDP
+    #[cfg(target_os = "linux")]
+    pub fn protocol(&self) -> Result<i32> {
+        self.get_int_option(SOL_SOCKET, libc::SO_PROTOCOL)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn protocol(&self) -> Result<i32> {
+        Err(SocketError::Unsupported(
+            "SO_PROTOCOL is only supported on Linux",
+        ))
+    }
+
+    // returns the local address, useful to find the ephemeral port picked for port 0
+    pub fn local_addr(&self) -> Result<Endpoint> {
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+
+        let res = unsafe {
+            getsockname(
+                self.fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        endpoint_from_storage(&addr, addr_len)
+    }
+
+    // returns the address of the peer the socket actually reached
+    pub fn peer_addr(&self) -> Result<Endpoint> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        match &self.peer {
+            Some(peer) => Ok(peer.clone()),
+            None => self.query_peer_addr(),
+        }
+    }
+
+    // asks the kernel, getpeername stays the source of truth for sockets accept didn't create
+    fn query_peer_addr(&self) -> Result<Endpoint> {
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+
+        let res = unsafe {
+            getpeername(
+                self.fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        endpoint_from_storage(&addr, addr_len)
+    }
+
+    // keeps reading until buf is full, unlike MSG_WAITALL each read still honours timeouts
+    pub fn recv_exact(&self, buf: &mut [u8]) -> Result<()> {
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            let received = self.recv(&mut buf[offset..])?;
+            if received == 0 {
+                return Err(SocketError::UnexpectedEof);
+            }
+            offset += received;
+        }
+
+        Ok(())
+    }
+
+    // payload bytes sent and received through this Socket value, a try_clone starts from zero
+    pub fn stats(&self) -> SocketStats {
+        SocketStats {
+            bytes_sent: self.counters.sent.load(Ordering::Relaxed),
+            bytes_received: self.counters.received.load(Ordering::Relaxed),
+        }
+    }
+
+    // reads one '\n' terminated line, e.g. an HTTP request line, and returns it without the
+    // "\n" or "\r\n", the data is peeked first so nothing past the newline is consumed, fails
+    // with InvalidInput when the line is longer than max bytes or not UTF-8, meant for
+    // blocking sockets since a WouldBlock midway drops the part already read
+    pub fn read_line(&self, max: usize) -> Result<String> {
+        let mut line = Vec::new();
+        let mut chunk = [0u8; 512];
+
+        loop {
+            // room for the rest of the line plus its "\r\n"
+            let want = max
+                .saturating_add(2)
+                .saturating_sub(line.len())
+                .min(chunk.len());
+            let peeked = self.peek(&mut chunk[..want])?;
+            if peeked == 0 {
+                return Err(SocketError::UnexpectedEof);
+            }
+
+            if let Some(pos) = chunk[..peeked].iter().position(|&b| b == b'\n') {
+                self.recv_exact(&mut chunk[..=pos])?;
+                line.extend_from_slice(&chunk[..pos]);
+                break;
+            }
+
+            self.recv_exact(&mut chunk[..peeked])?;
+            line.extend_from_slice(&chunk[..peeked]);
+            if line.len() > max.saturating_add(1) {
+                return Err(SocketError::InvalidInput("Line exceeds the maximum length"));
+            }
+        }
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > max {
+            return Err(SocketError::InvalidInput("Line exceeds the maximum length"));
+        }
+
+        String::from_utf8(line).map_err(|_| SocketError::InvalidInput("Line is not valid UTF-8"))
+    }
+
+    // sends msg as one frame: a 4-byte big-endian length followed by the payload
+    pub fn send_framed(&self, msg: &[u8]) -> Result<()> {
+        let len = u32::try_from(msg.len())
+            .map_err(|_| SocketError::InvalidInput("Frame is too large for its length prefix"))?;
+
+        self.send_all(&len.to_be_bytes())?;
+        self.send_all(msg)
+    }
+
+    // receives one frame written by send_framed, a length above max_frame_len fails with
+    // InvalidInput before anything is allocated, the payload is left unread so the stream
+    // can't be trusted afterwards
+    pub fn recv_framed(&self) -> Result<Vec<u8>> {
+        let mut prefix = [0u8; 4];
+        self.recv_exact(&mut prefix)?;
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len > self.max_frame_len {
+            return Err(SocketError::InvalidInput(
+                "Frame exceeds the maximum frame length",
+            ));
+        }
+
+        let mut msg = vec![0u8; len];
+        self.recv_exact(&mut msg)?;
+        Ok(msg)
+    }
+
+    // caps how large a frame recv_framed accepts, 16 MiB by default
+    pub fn set_max_frame_len(&mut self, len: usize) {
+        self.max_frame_len = len;
+    }
+
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    // splits a connected socket into a reading and a writing half, each owning its own
+    // descriptor, so one thread can read while another writes, e.g. behind a BufReader and a
+    // BufWriter, dropping a half shuts down its direction of the connection
+    pub fn split(self) -> Result<(ReadHalf, WriteHalf)> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let reader = self.try_clone()?;
+        Ok((ReadHalf { socket: reader }, WriteHalf { socket: self }))
+    }
+
+    // unlike close, the descriptor stays open so the other direction can still be used
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        if self.state != SocketState::Connected {
+            return Err(SocketError::InvalidState("Socket is not connected"));
+        }
+
+        let res = unsafe { shutdown(self.fd, how.as_raw()) };
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // closing an already closed socket is a no-op
+    pub fn close(&mut self) -> Result<()> {
+        if self.state == SocketState::Closed {
+            return Ok(());
+        }
+
+        let res = unsafe { close(self.fd) };
+        // the descriptor is released even if close reports an error, so never retry it
+        self.state = SocketState::Closed;
+
+        if res == -1 {
+            return Err(SocketError::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+// waits until at least one of sockets is readable and returns the indices of those that are,
+// an empty list means the timeout elapsed, None waits forever, no sockets at all returns an
+// empty list right away since nothing could ever wake the wait
+pub fn select_readable(sockets: &[&Socket], timeout: Option<Duration>) -> Result<Vec<usize>> {
+    if sockets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // fd_set is a fixed size bitmap, setting a bit past its end would corrupt the stack
+    if sockets
+        .iter()
+        .any(|sock| sock.fd < 0 || sock.fd as usize >= FD_SETSIZE)
+    {
+        return Err(SocketError::InvalidInput(
+            "Socket descriptor does not fit in an fd_set (FD_SETSIZE)",
+        ));
+    }
+
+    let deadline = timeout.map(|dur| Instant::now() + dur);
+    let nfds = sockets.iter().map(|sock| sock.fd + 1).max().unwrap_or(0);
+
+    loop {
+        let mut readfds: fd_set = unsafe { mem::zeroed() };
+        unsafe { FD_ZERO(&mut readfds) };
+        for sock in sockets {
+            unsafe { FD_SET(sock.fd, &mut readfds) };
+        }
+
+        // select may modify the timeval, so it is rebuilt from the deadline on every pass
+        let mut tv = deadline.map(|deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            timeval {
+                tv_sec: remaining.as_secs() as _,
+                tv_usec: remaining.subsec_micros() as _,
+            }
+        });
+        let tv_ptr = match tv.as_mut() {
+            Some(tv) => tv as *mut timeval,
+            None => ptr::null_mut(),
+        };
+
+        let res = unsafe { select(nfds, &mut readfds, ptr::null_mut(), ptr::null_mut(), tv_ptr) };
+
+        if res == -1 {
+            // a signal interrupted the wait, keep waiting for whatever time is left
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(SocketError::last_os_error());
+        }
+
+        return Ok(sockets
+            .iter()
+            .enumerate()
+            .filter(|(_, sock)| unsafe { FD_ISSET(sock.fd, &readfds) })
+            .map(|(index, _)| index)
+            .collect());
+    }
+}
+
+// keeps a socket corked while alive, see Socket::cork, dropping it sends whatever is buffered
+pub struct Cork<'a> {
+    socket: &'a Socket,
+}
+
+impl Drop for Cork<'_> {
+    fn drop(&mut self) {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let _ = self.socket.set_int_option(IPPROTO_TCP, CORK_OPTION, 0);
+    }
+}
+
+// blocks SIGPIPE for the current thread while alive, a SIGPIPE raised meanwhile is
+// discarded on drop, unless one was already pending before, then the mask is restored
+#[cfg(target_os = "linux")]
+struct SigpipeBlock {
+    previous: libc::sigset_t,
+    was_pending: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl SigpipeBlock {
+    fn new() -> Result<Self> {
+        let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+        let mut previous: libc::sigset_t = unsafe { mem::zeroed() };
+
+        let res = unsafe {
+            sigemptyset(&mut set);
+            sigaddset(&mut set, libc::SIGPIPE);
+            pthread_sigmask(libc::SIG_BLOCK, &set, &mut previous)
+        };
+        // pthread_sigmask returns the error instead of setting errno
+        if res != 0 {
+            return Err(SocketError::from_errno(res));
+        }
+
+        Ok(SigpipeBlock {
+            previous,
+            was_pending: sigpipe_pending(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for SigpipeBlock {
+    fn drop(&mut self) {
+        if !self.was_pending && sigpipe_pending() {
+            let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+            let timeout = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            unsafe {
+                sigemptyset(&mut set);
+                sigaddset(&mut set, libc::SIGPIPE);
+                sigtimedwait(&set, ptr::null_mut(), &timeout);
+            }
+        }
+
+        unsafe { pthread_sigmask(libc::SIG_SETMASK, &self.previous, ptr::null_mut()) };
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sigpipe_pending() -> bool {
+    let mut pending: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe { sigpending(&mut pending) == 0 && sigismember(&pending, libc::SIGPIPE) == 1 }
+}
+
+// a self-pipe: writing to one end makes the other readable, which wakes a poll
+struct Wakeup {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl Wakeup {
+    fn new() -> Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { pipe(fds.as_mut_ptr()) } == -1 {
+            return Err(SocketError::last_os_error());
+        }
+        let wakeup = Wakeup {
+            read_fd: fds[0],
+            write_fd: fds[1],
+        };
+
+        // the signal is never drained, so a full pipe just means it was already sent
+        for fd in fds {
+            let flags = unsafe { fcntl(fd, F_GETFL) };
+            if flags == -1
+                || unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) } == -1
+                || unsafe { fcntl(fd, F_SETFD, FD_CLOEXEC) } == -1
+            {
+                return Err(SocketError::last_os_error());
+            }
+        }
+
+        Ok(wakeup)
+    }
+}
+
+impl Drop for Wakeup {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.read_fd);
+            close(self.write_fd);
+        }
+    }
+}
+
+// stops a listener from another thread, see Socket::shutdown_handle
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    wakeup: Arc<Wakeup>,
+}
+
+impl ShutdownHandle {
+    // makes serve return Ok once the connection it may be handling is done and every accept
+    // on the listener fail from then on, safe to call more than once
+    pub fn shutdown(&self) -> Result<()> {
+        let byte = 1u8;
+        let res = unsafe { write(self.wakeup.write_fd, &byte as *const u8 as *const c_void, 1) };
+
+        if res == -1 {
+            let err = SocketError::last_os_error();
+            if err != SocketError::WouldBlock {
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ShutdownHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownHandle").finish_non_exhaustive()
+    }
+}
+
+// the receiving side of Socket::split
+pub struct ReadHalf {
+    socket: Socket,
+}
+
+// the sending side of Socket::split
+pub struct WriteHalf {
+    socket: Socket,
+}
+
+impl io::Read for ReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.socket.read(buf)
+    }
+}
+
+impl io::Write for WriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+impl AsRawFd for ReadHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.fd
+    }
+}
+
+impl AsRawFd for WriteHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.fd
+    }
+}
+
+// shutdown acts on the connection rather than the descriptor, so this also ends reading
+// through any other handle to the same socket
+impl Drop for ReadHalf {
+    fn drop(&mut self) {
+        let _ = self.socket.shutdown(Shutdown::Read);
+    }
+}
+
+// the peer sees EOF once the write half is gone, even while the read half is still open
+impl Drop for WriteHalf {
+    fn drop(&mut self) {
+        let _ = self.socket.shutdown(Shutdown::Write);
+    }
+}
+
+// iterator over connections accepted by a listening socket, see Socket::incoming
+pub struct Incoming<'a> {
+    listener: &'a Socket,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = Result<Socket>;
+
+    // ends instead of yielding the same error forever when the socket isn't listening
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.listener.state != SocketState::Listening {
+            return None;
+        }
+        Some(self.listener.accept().map(|(socket, _)| socket))
+    }
+}
+
+// addresses are looked up on every call, and left out when the socket doesn't have one
+impl fmt::Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Socket");
+        debug.field("fd", &self.fd).field("state", &self.state);
+
+        // Display keeps addresses readable, e.g. local: 127.0.0.1:8080
+        if let Ok(local) = self.local_addr() {
+            debug.field("local", &format_args!("{}", local));
+        }
+        if let Ok(peer) = self.peer_addr() {
+            debug.field("peer", &format_args!("{}", peer));
+        }
+
+        debug.finish()
+    }
+}
+
+impl io::Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(io::Error::from(io::ErrorKind::NotConnected));
+        }
+        self.recv(buf).map_err(io::Error::from)
+    }
+}
+
+impl io::Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state != SocketState::Connected {
+            return Err(io::Error::from(io::ErrorKind::NotConnected));
+        }
+        self.send(buf).map_err(io::Error::from)
+    }
+
+    // data is handed straight to the kernel, there is no user-space buffer to flush
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl FromRawFd for Socket {
+    // takes ownership of fd: the Socket closes it on Drop, so the caller must not close it too
+    // close-on-exec is left as the caller set it, unlike sockets this crate opens, so an
+    // inherited descriptor meant for an exec'd child stays inheritable
+    // family, type and state (connected, listening, bound or created) are read back from the
+    // kernel, falling back to an IPv4 stream socket in the Created state when the descriptor
+    // can't be inspected
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        let mut socket = Socket {
+            fd,
+            state: SocketState::Created,
+            family: AF_INET,
+            sock_type: SOCK_STREAM,
+            counters: Counters::default(),
+            peer: None,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            wakeup: None,
+        };
+
+        let mut addr: sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+        let res = unsafe {
+            getsockname(
+                fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+        // a port or path means bind already happened, an unbound socket reports port 0 or an
+        // empty path
+        let mut bound = false;
+        if res == 0 {
+            socket.family = addr.ss_family as i32;
+            bound = match endpoint_from_storage(&addr, addr_len) {
+                Ok(Endpoint::Unix(path)) => !path.as_os_str().is_empty(),
+                Ok(endpoint) => endpoint.port().is_some_and(|port| port != 0),
+                Err(_) => false,
+            };
+        }
+
+        if let Ok(sock_type) = socket.get_int_option(SOL_SOCKET, SO_TYPE) {
+            socket.sock_type = sock_type;
+        }
+
+        let mut addr_len = mem::size_of::<sockaddr_storage>() as socklen_t;
+        let connected = unsafe {
+            getpeername(
+                fd,
+                &mut addr as *mut sockaddr_storage as *mut sockaddr,
+                &mut addr_len,
+            )
+        } == 0;
+
+        if connected {
+            socket.state = SocketState::Connected;
+        } else if socket.get_int_option(SOL_SOCKET, SO_ACCEPTCONN) == Ok(1) {
+            socket.state = SocketState::Listening;
+        } else if bound {
+            socket.state = SocketState::Bound;
+        }
+
+        // macOS has no MSG_NOSIGNAL, so send relies on SO_NOSIGPIPE, the one part of init an
+        // adopted descriptor needs, from_raw_fd can't fail so one that refuses it (not a
+        // socket) is adopted as is
+        #[cfg(target_os = "macos")]
+        let _ = socket.set_int_option(SOL_SOCKET, libc::SO_NOSIGPIPE, 1);
+
+        socket
+    }
+}
+
+impl IntoRawFd for Socket {
+    // the caller becomes responsible for closing the descriptor
+    fn into_raw_fd(mut self) -> RawFd {
+        // marking the socket closed stops Drop from closing the descriptor we hand out
+        self.state = SocketState::Closed;
+        self.fd
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reads back the port the kernel assigned to a socket bound to port 0
+    fn bound_port(sock: &Socket) -> u16 {
+        // large enough for both families, sin_port and sin6_port share the same offset
+        let mut addr: sockaddr_in6 = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<sockaddr_in6>() as socklen_t;
+        let res = unsafe {
+            libc::getsockname(
+                sock.fd,
+                &mut addr as *mut sockaddr_in6 as *mut sockaddr,
+                &mut len,
+            )
+        };
+        assert_eq!(res, 0, "Failed to read bound address");
+        u16::from_be(addr.sin6_port)
+    }
+
+    // returns (client, server side of the accepted connection)
+    fn connected_pair() -> (Socket, Socket) {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+        let (server, _) = listener.accept().expect("Failed to accept connection");
+
+        (client, server)
+    }
+
+    // the test harness ignores SIGPIPE, this runs f with the default action restored so a
+    // write that raises it would actually kill the process, the lock keeps tests doing this
+    // in parallel from restoring each other's handler in the wrong order
+    fn with_default_sigpipe(f: impl FnOnce()) {
+        static SIGPIPE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _guard = SIGPIPE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        let previous = unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        unsafe { libc::signal(libc::SIGPIPE, previous) };
+
+        if let Err(panic) = res {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[test]
+    fn test_can_create_socket() {
+        let sock = Socket::new();
+        assert!(sock.is_ok(), "retured a file descriptor with a value of -1");
+    }
+
+    #[test]
+    fn test_bind_socket_to_port() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        // use 0 to allow the use to chose an avaiable ephepermal port
+        let _ = sock.bind(("0.0.0.0", 0));
+        // close the socket after use
+        sock.close().expect("Failed to close socket");
+    }
+
+    #[test]
+    fn test_bind_socket_invalid_fd() {
+        // passing invalid socket descriptor
+        let mut sock = Socket::new().expect("Failed to create socket");
+        // use 0 to allow the use to chose an avaiable ephepermal port
+        let res = sock.bind(("-dvddfvfdvdvd0.0.0.0", 0));
+
+        assert!(res.is_err(), "Should fail to bind scoket")
+    }
+
+    #[test]
+    fn test_bind_socket_port_in_use() {
+        let mut sock_1 = Socket::new().expect("Failed to create socket");
+        let mut sock_2 = Socket::new().expect("Failed to create socket");
+
+        // bind first soccket
+        let res1 = sock_1.bind(("0.0.0.0", 1150));
+        // bind second sock to the same port
+        let res2 = sock_2.bind(("0.0.0.0", 1150));
+
+        assert!(res1.is_ok(), "Failed to bind socket to port");
+        assert!(res2.is_err(), "Bound socket to port successfully");
+
+        sock_1.close().expect("Failed to close socket");
+        sock_2.close().expect("Failed to close socket");
+    }
+
+    #[test]
+    fn test_connect_to_listening_socket() {
+        let mut server = Socket::new().expect("Failed to create socket");
+        let mut client = Socket::new().expect("Failed to create socket");
+
+        server
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        server.listen(1).expect("Failed to listen on socket");
+
+        let res = client.connect(("127.0.0.1", bound_port(&server)));
+
+        assert!(res.is_ok(), "Failed to connect to listening socket");
+        assert_eq!(client.state, SocketState::Connected);
+    }
+
+    #[test]
+    fn test_connect_rejects_listening_socket() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        sock.bind(("0.0.0.0", 0)).expect("Failed to bind socket");
+        sock.listen(1).expect("Failed to listen on socket");
+
+        let res = sock.connect(("127.0.0.1", 1151));
+
+        assert!(
+            res.is_err(),
+            "Connected a socket that was already listening"
+        );
+    }
+
+    #[test]
+    fn test_send_returns_bytes_written() {
+        let (client, _server) = connected_pair();
+
+        let payload = b"hello";
+        let sent = client.send(payload).expect("Failed to send data");
+
+        assert_eq!(sent, payload.len());
+    }
+
+    #[test]
+    fn test_send_requires_connected_socket() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(
+            sock.send(b"hello").is_err(),
+            "Sent data on unconnected socket"
+        );
+    }
+
+    #[test]
+    fn test_send_recv_round_trip() {
+        let (client, server) = connected_pair();
+
+        client.send(b"hello").expect("Failed to send data");
+
+        let mut buf = [0u8; 16];
+        let received = server.recv(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(received, 5);
+        assert_eq!(&buf[..5], b"hello");
+    }
+
+    #[test]
+    fn test_recv_returns_zero_when_peer_closes() {
+        let (client, server) = connected_pair();
+        drop(client);
+
+        let mut buf = [0u8; 16];
+        let received = server.recv(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(received, 0);
+    }
+
+    #[test]
+    fn test_close_is_idempotent() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+
+        assert!(sock.close().is_ok(), "Failed to close socket");
+        assert!(sock.close().is_ok(), "Second close should be a no-op");
+        assert_eq!(sock.state, SocketState::Closed);
+    }
+
+    #[test]
+    fn test_shutdown_write_signals_eof_to_peer() {
+        let (client, server) = connected_pair();
+
+        client
+            .shutdown(Shutdown::Write)
+            .expect("Failed to shut down socket");
+
+        let mut buf = [0u8; 16];
+        let received = server.recv(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(received, 0);
+    }
+
+    #[test]
+    fn test_shutdown_keeps_read_half_open() {
+        let (client, server) = connected_pair();
+
+        client
+            .shutdown(Shutdown::Write)
+            .expect("Failed to shut down socket");
+        server.send(b"reply").expect("Failed to send data");
+
+        let mut buf = [0u8; 16];
+        let received = client.recv(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(&buf[..received], b"reply");
+    }
+
+    #[test]
+    fn test_bind_ipv6_loopback() {
+        let mut sock = Socket::new_v6().expect("Failed to create socket");
+
+        assert!(sock.bind(("::1", 0)).is_ok(), "Failed to bind to ::1");
+    }
+
+    #[test]
+    fn test_connect_over_ipv6_loopback() {
+        let mut server = Socket::new_v6().expect("Failed to create socket");
+        server.bind(("::1", 0)).expect("Failed to bind socket");
+        server.listen(1).expect("Failed to listen on socket");
+
+        let mut client = Socket::new_v6().expect("Failed to create socket");
+        client
+            .connect(("::1", bound_port(&server)))
+            .expect("Failed to connect over IPv6");
+        let (accepted, _) = server.accept().expect("Failed to accept connection");
+
+        client.send(b"v6").expect("Failed to send data");
+        let mut buf = [0u8; 4];
+        let received = accepted.recv(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf[..received], b"v6");
+    }
+
+    #[test]
+    fn test_bind_rejects_mismatched_family() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+
+        assert!(
+            sock.bind(("::1", 0)).is_err(),
+            "Bound an IPv4 socket to ::1"
+        );
+    }
+
+    #[test]
+    fn test_udp_send_to_recv_from() {
+        let mut receiver = Socket::new_udp().expect("Failed to create socket");
+        let mut sender = Socket::new_udp().expect("Failed to create socket");
+        receiver
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        sender
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+
+        let sent = sender
+            .send_to(b"ping", ("127.0.0.1", bound_port(&receiver)))
+            .expect("Failed to send datagram");
+        assert_eq!(sent, 4);
+
+        let mut buf = [0u8; 16];
+        let (received, from) = receiver
+            .recv_from(&mut buf)
+            .expect("Failed to receive datagram");
+
+        assert_eq!(&buf[..received], b"ping");
+        assert_eq!(from, Endpoint::V4(Ipv4Addr::LOCALHOST, bound_port(&sender)));
+    }
+
+    #[test]
+    fn test_send_to_rejects_stream_socket() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(
+            sock.send_to(b"ping", ("127.0.0.1", 9)).is_err(),
+            "Sent a datagram on a stream socket"
+        );
+    }
+
+    // unique path in the temp directory so parallel tests don't collide
+    fn temp_socket_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("berkeley_{}_{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_str()
+            .expect("Temp path is not valid UTF-8")
+            .to_owned()
+    }
+
+    #[test]
+    fn test_unix_socket_round_trip() {
+        let path = temp_socket_path("round_trip");
+
+        let mut server = Socket::new_unix().expect("Failed to create socket");
+        server.bind_unix(&path).expect("Failed to bind socket");
+        server.listen(1).expect("Failed to listen on socket");
+
+        let mut client = Socket::new_unix().expect("Failed to create socket");
+        client.connect_unix(&path).expect("Failed to connect");
+        let (accepted, _) = server.accept().expect("Failed to accept connection");
+
+        client.send(b"local").expect("Failed to send data");
+        let mut buf = [0u8; 16];
+        let received = accepted.recv(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf[..received], b"local");
+
+        server.close().expect("Failed to close socket");
+        std::fs::remove_file(&path).expect("Failed to unlink socket path");
+    }
+
+    #[test]
+    fn test_bind_unix_rejects_long_path() {
+        let mut sock = Socket::new_unix().expect("Failed to create socket");
+        let path = "a".repeat(200);
+
+        assert!(sock.bind_unix(&path).is_err(), "Bound to an over-long path");
+    }
+
+    #[test]
+    fn test_unix_path_length_limit() {
+        let max = unsafe { mem::zeroed::<sockaddr_un>() }.sun_path.len() - 1;
+
+        let mut sock = Socket::new_unix().expect("Failed to create socket");
+        assert!(matches!(
+            sock.connect_unix(&"a".repeat(max + 1)),
+            Err(SocketError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            sock.bind_unix("/tmp/bad\0path.sock"),
+            Err(SocketError::InvalidInput(_))
+        ));
+
+        // the longest path that fits still gets its terminating null byte
+        let addr = unix_sockaddr(&"a".repeat(max)).expect("Failed to build address");
+        assert_eq!(addr.sun_path[max], 0);
+    }
+
+    #[test]
+    fn test_nonblocking_accept_would_block() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+        listener
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+
+        let res = listener.accept();
+
+        assert_eq!(res.err(), Some(SocketError::WouldBlock));
+    }
+
+    #[test]
+    fn test_nonblocking_recv_would_block() {
+        let (_client, server) = connected_pair();
+        server
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+
+        let mut buf = [0u8; 16];
+        let res = server.recv(&mut buf);
+
+        assert_eq!(res.err(), Some(SocketError::WouldBlock));
+    }
+
+    #[test]
+    fn test_reuse_address_allows_rebinding_released_port() {
+        let mut first = Socket::new().expect("Failed to create socket");
+        first
+            .set_reuse_address(true)
+            .expect("Failed to set SO_REUSEADDR");
+        first.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        first.listen(1).expect("Failed to listen on socket");
+        let port = bound_port(&first);
+
+        // closing the accepted side first leaves the port in TIME_WAIT
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+        let (mut accepted, _) = first.accept().expect("Failed to accept connection");
+        accepted.close().expect("Failed to close socket");
+        first.close().expect("Failed to close socket");
+
+        let mut second = Socket::new().expect("Failed to create socket");
+        second
+            .set_reuse_address(true)
+            .expect("Failed to set SO_REUSEADDR");
+
+        assert!(
+            second.bind(("127.0.0.1", port)).is_ok(),
+            "Failed to rebind a released port"
+        );
+    }
+
+    #[test]
+    fn test_reuse_port_allows_shared_bind() {
+        let mut sock_1 = Socket::new().expect("Failed to create socket");
+        let mut sock_2 = Socket::new().expect("Failed to create socket");
+        sock_1
+            .set_reuse_port(true)
+            .expect("Failed to set SO_REUSEPORT");
+        sock_2
+            .set_reuse_port(true)
+            .expect("Failed to set SO_REUSEPORT");
+
+        sock_1
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let res = sock_2.bind(("127.0.0.1", bound_port(&sock_1)));
+
+        assert!(res.is_ok(), "Failed to bind second socket to shared port");
+    }
+
+    #[test]
+    fn test_nodelay_round_trip() {
+        let (client, _server) = connected_pair();
+
+        client.set_nodelay(true).expect("Failed to set TCP_NODELAY");
+
+        assert!(client.nodelay().expect("Failed to read TCP_NODELAY"));
+    }
+
+    #[test]
+    fn test_read_timeout_fires() {
+        let (_client, server) = connected_pair();
+        server
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .expect("Failed to set read timeout");
+
+        let mut buf = [0u8; 16];
+        let res = server.recv(&mut buf);
+
+        assert_eq!(res.err(), Some(SocketError::TimedOut));
+    }
+
+    #[test]
+    fn test_recv_from_timeout_reports_timed_out() {
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        sock.set_read_timeout(Some(Duration::from_millis(100)))
+            .expect("Failed to set read timeout");
+
+        let mut buf = [0u8; 16];
+        assert_eq!(sock.recv_from(&mut buf).err(), Some(SocketError::TimedOut));
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        assert_eq!(
+            sock.recv_from_with_info(&mut buf).err(),
+            Some(SocketError::TimedOut)
+        );
+    }
+
+    #[test]
+    fn test_read_timeout_rejects_zero_duration() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(
+            sock.set_read_timeout(Some(Duration::ZERO)).is_err(),
+            "Accepted a zero timeout"
+        );
+    }
+
+    #[test]
+    fn test_linger_round_trip() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert_eq!(sock.linger().expect("Failed to read SO_LINGER"), None);
+
+        sock.set_linger(Some(Duration::ZERO))
+            .expect("Failed to set SO_LINGER");
+        assert_eq!(
+            sock.linger().expect("Failed to read SO_LINGER"),
+            Some(Duration::ZERO)
+        );
+
+        sock.set_linger(Some(Duration::from_secs(5)))
+            .expect("Failed to set SO_LINGER");
+        assert_eq!(
+            sock.linger().expect("Failed to read SO_LINGER"),
+            Some(Duration::from_secs(5))
+        );
+
+        // a sub-second linger must not become the reset-on-close of zero
+        sock.set_linger(Some(Duration::from_millis(500)))
+            .expect("Failed to set SO_LINGER");
+        assert_eq!(
+            sock.linger().expect("Failed to read SO_LINGER"),
+            Some(Duration::from_secs(1))
+        );
+
+        assert_eq!(
+            sock.set_linger(Some(Duration::from_secs(u64::MAX))),
+            Err(SocketError::InvalidInput("Linger time is too large"))
+        );
+    }
+
+    #[test]
+    fn test_buffer_sizes_are_at_least_requested() {
+        let sock = Socket::new().expect("Failed to create socket");
+        let requested = 64 * 1024;
+
+        sock.set_send_buffer_size(requested)
+            .expect("Failed to set SO_SNDBUF");
+        sock.set_recv_buffer_size(requested)
+            .expect("Failed to set SO_RCVBUF");
+
+        // the kernel is free to round the size up (Linux doubles it for bookkeeping)
+        assert!(sock.send_buffer_size().expect("Failed to read SO_SNDBUF") >= requested);
+        assert!(sock.recv_buffer_size().expect("Failed to read SO_RCVBUF") >= requested);
+    }
+
+    #[test]
+    fn test_raw_option_round_trip() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        sock.set_option_raw(SOL_SOCKET, SO_REUSEADDR, &1i32.to_ne_bytes())
+            .expect("Failed to set raw option");
+
+        let mut buf = [0u8; 4];
+        let len = sock
+            .get_option_raw(SOL_SOCKET, SO_REUSEADDR, &mut buf)
+            .expect("Failed to read raw option");
+
+        assert_eq!(len, 4);
+        assert_ne!(i32::from_ne_bytes(buf), 0);
+    }
+
+    #[test]
+    fn test_accept_reports_peer_address() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let client_port = bound_port(&client);
+        client
+            .connect(("127.0.0.1", bound_port(&listener)))
+            .expect("Failed to connect");
+
+        let (_accepted, peer) = listener.accept().expect("Failed to accept connection");
+
+        assert_eq!(peer, Endpoint::V4(Ipv4Addr::LOCALHOST, client_port));
+    }
+
+    #[test]
+    fn test_local_addr_reports_ephemeral_port() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        sock.bind(("0.0.0.0", 0)).expect("Failed to bind socket");
+
+        match sock.local_addr().expect("Failed to read local address") {
+            Endpoint::V4(ip, port) => {
+                assert_eq!(ip, Ipv4Addr::UNSPECIFIED);
+                assert_ne!(port, 0);
+            }
+            other => panic!("Expected an IPv4 endpoint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_peer_addr_reports_listener_port() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+        let listener_port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", listener_port))
+            .expect("Failed to connect");
+
+        let peer = client.peer_addr().expect("Failed to read peer address");
+
+        assert_eq!(peer, Endpoint::V4(Ipv4Addr::LOCALHOST, listener_port));
+    }
+
+    #[test]
+    fn test_peer_addr_requires_connected_socket() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(sock.peer_addr().is_err(), "Read peer of unconnected socket");
+    }
+
+    #[test]
+    fn test_as_raw_fd_is_valid() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(sock.as_raw_fd() >= 0, "Socket has an invalid descriptor");
+    }
+
+    #[test]
+    fn test_from_raw_fd_adopts_descriptor() {
+        let fd = unsafe { socket(AF_INET6, SOCK_DGRAM, 0) };
+        assert_ne!(fd, -1, "Failed to create raw socket");
+
+        let sock = unsafe { Socket::from_raw_fd(fd) };
+
+        assert_eq!(sock.as_raw_fd(), fd);
+        assert_eq!(sock.family, AF_INET6);
+        assert_eq!(sock.sock_type, SOCK_DGRAM);
+        assert_eq!(sock.state, SocketState::Created);
+    }
+
+    #[test]
+    fn test_from_raw_fd_detects_connected_socket() {
+        let (client, _server) = connected_pair();
+        let fd = unsafe { libc::dup(client.as_raw_fd()) };
+
+        let adopted = unsafe { Socket::from_raw_fd(fd) };
+
+        assert_eq!(adopted.state, SocketState::Connected);
+        adopted
+            .send(b"hi")
+            .expect("Failed to send on adopted socket");
+    }
+
+    #[test]
+    fn test_from_raw_fd_detects_bound_socket() {
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+
+        let mut adopted = unsafe { Socket::from_raw_fd(sock.into_raw_fd()) };
+        assert_eq!(adopted.state, SocketState::Bound);
+        assert!(matches!(
+            adopted.bind(("127.0.0.1", 0)),
+            Err(SocketError::InvalidState(_))
+        ));
+
+        let fresh = Socket::new().expect("Failed to create socket");
+        let adopted = unsafe { Socket::from_raw_fd(fresh.into_raw_fd()) };
+        assert_eq!(adopted.state, SocketState::Created);
+    }
+
+    #[test]
+    fn test_into_raw_fd_releases_ownership() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        // Socket is dropped here, the descriptor must survive it
+        let fd = sock.into_raw_fd();
+
+        assert_ne!(
+            unsafe { fcntl(fd, libc::F_GETFD) },
+            -1,
+            "Drop closed a released descriptor"
+        );
+        assert_eq!(
+            unsafe { close(fd) },
+            0,
+            "Failed to close released descriptor"
+        );
+    }
+
+    #[test]
+    fn test_read_line_through_bufreader() {
+        use std::io::BufRead;
+
+        let (client, server) = connected_pair();
+        client
+            .send(b"first line\nsecond")
+            .expect("Failed to send data");
+
+        let mut reader = std::io::BufReader::new(server);
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("Failed to read line");
+
+        assert_eq!(line, "first line\n");
+    }
+
+    #[test]
+    fn test_read_requires_connected_socket() {
+        use std::io::Read;
+
+        let mut sock = Socket::new().expect("Failed to create socket");
+        let mut buf = [0u8; 4];
+
+        let err = sock
+            .read(&mut buf)
+            .expect_err("Read from unconnected socket");
+
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn test_write_all_delivers_payload() {
+        use std::io::{Read, Write};
+
+        let (mut client, mut server) = connected_pair();
+        let payload = vec![7u8; 32 * 1024];
+
+        client.write_all(&payload).expect("Failed to write payload");
+        client
+            .shutdown(Shutdown::Write)
+            .expect("Failed to shut down socket");
+
+        let mut received = Vec::new();
+        server
+            .read_to_end(&mut received)
+            .expect("Failed to read payload");
+
+        assert_eq!(received, payload);
+    }
+
+    #[test]
+    fn test_incoming_yields_each_connection() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(8).expect("Failed to listen on socket");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let clients = std::thread::spawn(move || {
+            (0..3)
+                .map(|_| {
+                    let mut client = Socket::new().expect("Failed to create socket");
+                    client
+                        .connect(("127.0.0.1", port))
+                        .expect("Failed to connect");
+                    client
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let accepted = listener
+            .incoming()
+            .take(3)
+            .collect::<Result<Vec<_>>>()
+            .expect("Failed to accept connection");
+        clients.join().expect("Client thread panicked");
+
+        assert_eq!(accepted.len(), 3);
+    }
+
+    #[test]
+    fn test_incoming_ends_when_not_listening() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(sock.incoming().next().is_none());
+    }
+
+    #[test]
+    fn test_send_all_delivers_large_buffer() {
+        let (client, server) = connected_pair();
+        let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| i as u8).collect();
+        let expected = payload.len();
+
+        // read concurrently, otherwise the socket buffers fill up and send_all blocks forever
+        let reader = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = [0u8; 64 * 1024];
+            loop {
+                let n = server.recv(&mut buf).expect("Failed to receive data");
+                if n == 0 {
+                    break received;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+        });
+
+        client.send_all(&payload).expect("Failed to send payload");
+        client
+            .shutdown(Shutdown::Write)
+            .expect("Failed to shut down socket");
+        let received = reader.join().expect("Reader thread panicked");
+
+        assert_eq!(received.len(), expected);
+        assert!(received == payload, "Received data does not match");
+    }
+
+    #[test]
+    fn test_send_all_empty_buffer() {
+        let (client, _server) = connected_pair();
+
+        assert!(client.send_all(&[]).is_ok(), "Failed to send empty buffer");
+    }
+
+    #[test]
+    fn test_recv_exact_fills_buffer() {
+        let (client, server) = connected_pair();
+        client.send_all(b"0123456789").expect("Failed to send data");
+
+        let mut buf = [0u8; 10];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(&buf, b"0123456789");
+    }
+
+    #[test]
+    fn test_recv_exact_errors_on_early_eof() {
+        let (client, server) = connected_pair();
+        client.send_all(b"short").expect("Failed to send data");
+        drop(client);
+
+        let mut buf = [0u8; 10];
+
+        assert!(
+            server.recv_exact(&mut buf).is_err(),
+            "Filled buffer past EOF"
+        );
+    }
+
+    #[test]
+    fn test_peek_does_not_consume_data() {
+        let (client, server) = connected_pair();
+        client.send_all(b"HEADbody").expect("Failed to send data");
+
+        let mut peeked = [0u8; 4];
+        let n = server.peek(&mut peeked).expect("Failed to peek data");
+        assert_eq!(&peeked[..n], b"HEAD");
+
+        let mut buf = [0u8; 4];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"HEAD");
+    }
+
+    #[test]
+    fn test_double_bind_reports_addr_in_use() {
+        let mut sock_1 = Socket::new().expect("Failed to create socket");
+        let mut sock_2 = Socket::new().expect("Failed to create socket");
+        sock_1
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = sock_1
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let res = sock_2.bind(("127.0.0.1", port));
+
+        assert!(
+            matches!(res, Err(SocketError::AddrInUse)),
+            "Expected AddrInUse, got {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_send_on_unconnected_socket_is_invalid_state() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        assert!(matches!(
+            sock.send(b"hello"),
+            Err(SocketError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn test_bind_error_contains_os_reason() {
+        let mut sock_1 = Socket::new().expect("Failed to create socket");
+        let mut sock_2 = Socket::new().expect("Failed to create socket");
+        sock_1
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = sock_1
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let err = sock_2
+            .bind(("127.0.0.1", port))
+            .expect_err("Bound socket to port in use");
+        let msg = err.to_string();
+
+        assert!(msg.contains("in use"), "Missing OS reason: {}", msg);
+        assert!(msg.contains("os error"), "Missing errno: {}", msg);
+    }
+
+    #[test]
+    fn test_uncategorized_error_carries_errno() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        // no such option, the kernel rejects it with an errno we don't have a variant for
+        let err = sock
+            .set_option_raw(SOL_SOCKET, -1, &1i32.to_ne_bytes())
+            .expect_err("Set a nonexistent option");
+
+        assert!(matches!(err, SocketError::Os(code) if code != 0));
+        assert!(
+            err.to_string().contains("os error"),
+            "Missing errno: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_connect_host_by_name() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+
+        assert!(
+            client.connect_host("localhost", port).is_ok(),
+            "Failed to connect to localhost"
+        );
+    }
+
+    #[test]
+    fn test_connect_timeout_does_not_hang() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        let start = Instant::now();
+
+        // nothing answers on this non-routable address, so the handshake never completes
+        let res = sock.connect_timeout(("10.255.255.1", 80), Duration::from_millis(200));
+
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "connect_timeout hung"
+        );
+        // some sandboxed networks accept every connection, and a host without any route
+        // fails straight away, so only a timeout is checked for when the attempt didn't succeed
+        match res {
+            Ok(()) | Err(SocketError::TimedOut) => {}
+            Err(SocketError::Os(code))
+                if code == libc::ENETUNREACH || code == libc::EHOSTUNREACH => {}
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_connect_timeout_to_listener() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen on socket");
+        let port = listener
+            .local_addr()
+            .expect("Failed to read local address")
+            .port()
+            .expect("Socket has no port");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect_timeout(("127.0.0.1", port), Duration::from_secs(1))
+            .expect("Failed to connect");
+
+        assert_eq!(client.state, SocketState::Connected);
+        let flags = unsafe { fcntl(client.fd, F_GETFL) };
+        assert_eq!(flags & O_NONBLOCK, 0, "Socket was left non-blocking");
+    }
+
+    #[test]
+    fn test_try_clone_shares_connection() {
+        let (client, server) = connected_pair();
+        let mut clone = client.try_clone().expect("Failed to clone socket");
+        assert_ne!(clone.fd, client.fd);
+
+        clone.send_all(b"from clone").expect("Failed to send data");
+        let mut buf = [0u8; 10];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"from clone");
+
+        // closing the clone must not tear down the original handle
+        clone.close().expect("Failed to close clone");
+        server.send_all(b"reply").expect("Failed to send data");
+        let mut buf = [0u8; 5];
+        client.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"reply");
+    }
+
+    #[test]
+    fn test_debug_shows_state_and_addresses() {
+        let (client, _server) = connected_pair();
+        let peer_port = client
+            .peer_addr()
+            .expect("Failed to read peer address")
+            .port()
+            .expect("Socket has no port");
+
+        let debug = format!("{:?}", client);
+
+        assert!(debug.contains("Connected"), "Missing state: {}", debug);
+        assert!(
+            debug.contains(&format!("127.0.0.1:{}", peer_port)),
+            "Missing peer address: {}",
+            debug
+        );
+    }
+
+    #[test]
+    fn test_state_helpers_follow_lifecycle() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        assert_eq!(sock.state(), &SocketState::Created);
+        assert!(!sock.is_listening());
+
+        sock.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        assert!(!sock.is_listening());
+
+        sock.listen(1).expect("Failed to listen on socket");
+        assert!(sock.is_listening());
+        assert!(!sock.is_connected());
+
+        sock.close().expect("Failed to close socket");
+        assert!(sock.is_closed());
+    }
+
+    #[test]
+    fn test_vectored_round_trip() {
+        let (client, server) = connected_pair();
+        let header = 5u32.to_be_bytes();
+
+        let sent = client
+            .send_vectored(&[&header, b"hello"])
+            .expect("Failed to send data");
+        assert_eq!(sent, 9);
+
+        let mut head = [0u8; 4];
+        let mut body = [0u8; 5];
+        let received = server
+            .recv_vectored(&mut [&mut head, &mut body])
+            .expect("Failed to receive data");
+
+        assert_eq!(received, 9);
+        assert_eq!(u32::from_be_bytes(head), 5);
+        assert_eq!(&body, b"hello");
+    }
+
+    #[test]
+    fn test_join_and_leave_multicast_group() {
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.bind(("0.0.0.0", 0)).expect("Failed to bind socket");
+        let group = Ipv4Addr::new(239, 0, 0, 1);
+
+        sock.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)
+            .expect("Failed to join multicast group");
+        sock.leave_multicast_v4(group, Ipv4Addr::UNSPECIFIED)
+            .expect("Failed to leave multicast group");
+    }
+
+    #[test]
+    fn test_join_multicast_rejects_stream_socket() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        let res = sock.join_multicast_v4(Ipv4Addr::new(239, 0, 0, 1), Ipv4Addr::UNSPECIFIED);
+
+        assert!(matches!(res, Err(SocketError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_broadcast_round_trip() {
+        let sock = Socket::new_udp().expect("Failed to create socket");
+        assert!(!sock.broadcast().expect("Failed to read SO_BROADCAST"));
+
+        sock.set_broadcast(true)
+            .expect("Failed to set SO_BROADCAST");
+
+        assert!(sock.broadcast().expect("Failed to read SO_BROADCAST"));
+    }
+
+    #[test]
+    fn test_ttl_round_trip() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        sock.set_ttl(5).expect("Failed to set IP_TTL");
+
+        assert_eq!(sock.ttl().expect("Failed to read IP_TTL"), 5);
+        assert!(sock.set_ttl(256).is_err(), "Accepted a TTL above 255");
+    }
+
+    #[test]
+    fn test_multicast_ttl_and_loop_round_trip() {
+        let sock = Socket::new_udp().expect("Failed to create socket");
+
+        sock.set_multicast_ttl_v4(3)
+            .expect("Failed to set IP_MULTICAST_TTL");
+        sock.set_multicast_loop_v4(false)
+            .expect("Failed to set IP_MULTICAST_LOOP");
+
+        assert_eq!(
+            sock.multicast_ttl_v4()
+                .expect("Failed to read IP_MULTICAST_TTL"),
+            3
+        );
+        assert!(!sock
+            .multicast_loop_v4()
+            .expect("Failed to read IP_MULTICAST_LOOP"));
+    }
+
+    #[test]
+    fn test_poll_readable_after_client_connects() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+
+        assert!(!listener
+            .poll_readable(Some(Duration::from_millis(10)))
+            .expect("Failed to poll listener"));
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", bound_port(&listener)))
+            .expect("Failed to connect");
+
+        assert!(listener
+            .poll_readable(Some(Duration::from_secs(1)))
+            .expect("Failed to poll listener"));
+        assert!(client
+            .poll_writable(Some(Duration::from_secs(1)))
+            .expect("Failed to poll client"));
+    }
+
+    #[test]
+    fn test_select_readable_reports_only_ready_listener() {
+        let mut idle = Socket::new().expect("Failed to create socket");
+        idle.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        idle.listen(1).expect("Failed to listen");
+
+        let mut busy = Socket::new().expect("Failed to create socket");
+        busy.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        busy.listen(1).expect("Failed to listen");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", bound_port(&busy)))
+            .expect("Failed to connect");
+
+        let ready = select_readable(&[&idle, &busy], Some(Duration::from_secs(1)))
+            .expect("Failed to select");
+        assert_eq!(ready, vec![1]);
+    }
+
+    #[test]
+    fn test_select_readable_with_no_sockets_returns_at_once() {
+        assert_eq!(select_readable(&[], None), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_select_readable_times_out() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+
+        let ready = select_readable(&[&listener], Some(Duration::from_millis(10)))
+            .expect("Failed to select");
+        assert!(ready.is_empty());
+    }
+
+    #[test]
+    fn test_nonblocking_send_would_block_when_buffer_full() {
+        let (client, _server) = connected_pair();
+        client
+            .set_send_buffer_size(4096)
+            .expect("Failed to set send buffer size");
+        client
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+
+        // nobody reads on the other end, so the send and receive buffers eventually fill up
+        let chunk = [0u8; 65536];
+        let mut res = Ok(0);
+        for _ in 0..10_000 {
+            res = client.send(&chunk);
+            if res.is_err() {
+                break;
+            }
+        }
+
+        assert_eq!(res.err(), Some(SocketError::WouldBlock));
+    }
+
+    #[test]
+    fn test_nonblocking_connect_completes_through_poll() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+
+        match client.connect(("127.0.0.1", bound_port(&listener))) {
+            // loopback connects are allowed to finish right away
+            Ok(()) => {}
+            Err(SocketError::InProgress) => {
+                assert!(client
+                    .poll_writable(Some(Duration::from_secs(1)))
+                    .expect("Failed to poll client"));
+                client
+                    .take_connect_error()
+                    .expect("Connect did not succeed");
+            }
+            Err(err) => panic!("Unexpected connect error: {}", err),
+        }
+
+        assert!(client.is_connected());
+        client.send(b"ping").expect("Failed to send");
+    }
+
+    fn has_cloexec(sock: &Socket) -> bool {
+        let flags = unsafe { fcntl(sock.as_raw_fd(), F_GETFD) };
+        assert_ne!(flags, -1, "Failed to read descriptor flags");
+        flags & FD_CLOEXEC != 0
+    }
+
+    #[test]
+    fn test_new_socket_is_cloexec() {
+        let sock = Socket::new().expect("Failed to create socket");
+        assert!(has_cloexec(&sock));
+
+        sock.set_cloexec(false).expect("Failed to clear FD_CLOEXEC");
+        assert!(!has_cloexec(&sock));
+    }
+
+    #[test]
+    fn test_accepted_socket_is_cloexec() {
+        let (client, server) = connected_pair();
+        assert!(has_cloexec(&client));
+        assert!(has_cloexec(&server));
+    }
+
+    #[test]
+    fn test_accept_with_sets_requested_modes() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(2).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let mut first = Socket::new().expect("Failed to create socket");
+        first
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+        let (server, _) = listener.accept_with(true, false).expect("Failed to accept");
+
+        let flags = unsafe { fcntl(server.as_raw_fd(), F_GETFL) };
+        assert_ne!(flags & O_NONBLOCK, 0);
+        assert!(!has_cloexec(&server));
+
+        let mut buf = [0u8; 4];
+        assert_eq!(server.recv(&mut buf).err(), Some(SocketError::WouldBlock));
+
+        let mut second = Socket::new().expect("Failed to create socket");
+        second
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+        let (server, _) = listener.accept_with(false, true).expect("Failed to accept");
+
+        let flags = unsafe { fcntl(server.as_raw_fd(), F_GETFL) };
+        assert_eq!(flags & O_NONBLOCK, 0);
+        assert!(has_cloexec(&server));
+    }
+
+    #[test]
+    fn test_dual_stack_listener_accepts_ipv4_client() {
+        let mut listener = Socket::new_v6().expect("Failed to create socket");
+        listener
+            .set_only_v6(false)
+            .expect("Failed to clear IPV6_V6ONLY");
+        assert!(!listener.only_v6().expect("Failed to read IPV6_V6ONLY"));
+        listener.bind(("::", 0)).expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", bound_port(&listener)))
+            .expect("Failed to connect over IPv4");
+        let (accepted, _) = listener.accept().expect("Failed to accept connection");
+
+        client.send(b"v4").expect("Failed to send data");
+        let mut buf = [0u8; 4];
+        let received = accepted.recv(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf[..received], b"v4");
+    }
+
+    #[test]
+    fn test_only_v6_round_trip() {
+        let sock = Socket::new_v6().expect("Failed to create socket");
+        sock.set_only_v6(true).expect("Failed to set IPV6_V6ONLY");
+        assert!(sock.only_v6().expect("Failed to read IPV6_V6ONLY"));
+
+        let v4 = Socket::new().expect("Failed to create socket");
+        assert!(matches!(
+            v4.set_only_v6(true),
+            Err(SocketError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_to_closed_peer_errors_instead_of_sigpipe() {
+        with_default_sigpipe(|| {
+            let (client, mut server) = connected_pair();
+            server.close().expect("Failed to close socket");
+
+            // the first write can still succeed, the peer answers it with a reset
+            let mut res = Ok(0);
+            for _ in 0..100 {
+                res = client.send(b"data");
+                if res.is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert!(res.is_err());
+            assert!(client.send_vectored(&[b"more"]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_send_file_transfers_contents() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("bs_sendfile_{}", std::process::id()));
+        let contents: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        File::create(&path)
+            .and_then(|mut file| file.write_all(&contents))
+            .expect("Failed to write temp file");
+        let file = File::open(&path).expect("Failed to open temp file");
+
+        let (client, server) = connected_pair();
+        let mut offset = 0;
+        while offset < contents.len() {
+            offset += client
+                .send_file(&file, offset as u64, contents.len() - offset)
+                .expect("Failed to send file");
+        }
+        let _ = std::fs::remove_file(&path);
+
+        let mut received = vec![0u8; contents.len()];
+        server
+            .recv_exact(&mut received)
+            .expect("Failed to receive file");
+        assert_eq!(received, contents);
+    }
+
+    #[test]
+    fn test_send_file_to_closed_peer_errors_instead_of_sigpipe() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("bs_sendfile_epipe_{}", std::process::id()));
+        File::create(&path)
+            .and_then(|mut file| file.write_all(&[7u8; 4096]))
+            .expect("Failed to write temp file");
+        let file = File::open(&path).expect("Failed to open temp file");
+        let _ = std::fs::remove_file(&path);
+
+        with_default_sigpipe(|| {
+            let (client, mut server) = connected_pair();
+            server.close().expect("Failed to close socket");
+
+            // the first transfer can still succeed, the peer answers it with a reset
+            let mut res = Ok(0);
+            for _ in 0..100 {
+                res = client.send_file(&file, 0, 4096);
+                if res.is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert!(res.is_err());
+        });
+    }
+
+    #[test]
+    fn test_cork_batches_small_sends() {
+        let (client, server) = connected_pair();
+
+        {
+            let _cork = client.cork().expect("Failed to cork socket");
+            client.send_all(b"header:").expect("Failed to send header");
+            client.send_all(b"body").expect("Failed to send body");
+        }
+
+        let mut buf = [0u8; 11];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"header:body");
+    }
+
+    #[test]
+    fn test_keepalive_round_trip() {
+        let sock = Socket::new().expect("Failed to create socket");
+        let params = KeepaliveParams {
+            idle: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+            retries: 5,
+        };
+
+        sock.set_keepalive(Some(params))
+            .expect("Failed to enable keepalive");
+        assert_eq!(sock.get_int_option(SOL_SOCKET, SO_KEEPALIVE), Ok(1));
+        assert_eq!(sock.keepalive(), Ok(Some(params)));
+
+        sock.set_keepalive(None)
+            .expect("Failed to disable keepalive");
+        assert_eq!(sock.keepalive(), Ok(None));
+    }
+
+    #[test]
+    fn test_keepalive_rejects_zero_idle() {
+        let sock = Socket::new().expect("Failed to create socket");
+        let res = sock.set_keepalive(Some(KeepaliveParams {
+            idle: Duration::ZERO,
+            interval: Duration::from_secs(10),
+            retries: 5,
+        }));
+
+        assert!(matches!(res, Err(SocketError::InvalidInput(_))));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_user_timeout_round_trip() {
+        let sock = Socket::new().expect("Failed to create socket");
+        sock.set_user_timeout(Duration::from_millis(5000))
+            .expect("Failed to set TCP_USER_TIMEOUT");
+
+        assert_eq!(
+            sock.user_timeout()
+                .expect("Failed to read TCP_USER_TIMEOUT"),
+            Duration::from_millis(5000)
+        );
+    }
+
+    #[test]
+    fn test_available_reports_queued_bytes() {
+        let (client, server) = connected_pair();
+        assert_eq!(server.available(), Ok(0));
+
+        client.send_all(b"7 bytes").expect("Failed to send data");
+        assert!(server
+            .poll_readable(Some(Duration::from_secs(1)))
+            .expect("Failed to poll server"));
+
+        assert_eq!(server.available(), Ok(7));
+    }
+
+    #[test]
+    fn test_pair_is_connected_both_ways() {
+        let (first, second) = Socket::pair().expect("Failed to create socket pair");
+        assert!(first.is_connected());
+        assert!(second.is_connected());
+
+        first.send_all(b"ping").expect("Failed to send data");
+        let mut buf = [0u8; 4];
+        second.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"ping");
+
+        second.send_all(b"pong").expect("Failed to send data");
+        first.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[test]
+    fn test_send_fd_passes_pipe_across_pair() {
+        use std::io::{Read, Write};
+
+        let mut pipe_fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(pipe_fds.as_mut_ptr()) }, 0);
+        let (read_end, write_end) = unsafe {
+            (
+                File::from_raw_fd(pipe_fds[0]),
+                File::from_raw_fd(pipe_fds[1]),
+            )
+        };
+
+        let (sender, receiver) = Socket::pair().expect("Failed to create socket pair");
+        sender
+            .send_fd(read_end.as_raw_fd())
+            .expect("Failed to send descriptor");
+        drop(read_end);
+
+        let fd = receiver.recv_fd().expect("Failed to receive descriptor");
+        let mut received = unsafe { File::from_raw_fd(fd) };
+
+        (&write_end)
+            .write_all(b"hello")
+            .expect("Failed to write to pipe");
+        drop(write_end);
+
+        let mut contents = String::new();
+        received
+            .read_to_string(&mut contents)
+            .expect("Failed to read from passed descriptor");
+        assert_eq!(contents, "hello");
+    }
+
+    // sends one byte carrying all of fds in a single SCM_RIGHTS message, which send_fd never
+    // does
+    fn send_raw_fds(sock: &Socket, fds: &[RawFd]) {
+        let byte = [0u8; 1];
+        let iov = iovec {
+            iov_base: byte.as_ptr() as *mut c_void,
+            iov_len: byte.len(),
+        };
+        let data_len = mem::size_of_val(fds) as u32;
+        let mut control = vec![0u64; unsafe { CMSG_SPACE(data_len) } as usize / 8 + 1];
+
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &iov as *const iovec as *mut iovec;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = unsafe { CMSG_SPACE(data_len) } as _;
+
+        unsafe {
+            let cmsg = CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = SOL_SOCKET;
+            (*cmsg).cmsg_type = SCM_RIGHTS;
+            (*cmsg).cmsg_len = CMSG_LEN(data_len) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), CMSG_DATA(cmsg) as *mut c_int, fds.len());
+        }
+
+        assert_eq!(unsafe { sendmsg(sock.fd, &msg, 0) }, 1);
+    }
+
+    // a pipe whose write end only lives on in the descriptors under test, reading it hits
+    // EOF once all of them are closed, the read end is non-blocking so a leak fails the test
+    // with WouldBlock instead of hanging it
+    fn pipe_reaches_eof(read_end: File) -> bool {
+        use std::io::Read;
+
+        let mut read_end = read_end;
+        let mut buf = Vec::new();
+        read_end.read_to_end(&mut buf).is_ok() && buf.is_empty()
+    }
+
+    fn pipe_pair() -> (File, File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        assert_ne!(unsafe { fcntl(fds[0], F_SETFL, O_NONBLOCK) }, -1);
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+
+    #[test]
+    fn test_recv_fd_closes_extra_descriptors() {
+        let (sender, receiver) = Socket::pair().expect("Failed to create socket pair");
+        let kept = File::open("/dev/null").expect("Failed to open /dev/null");
+        let (read_end, write_end) = pipe_pair();
+
+        send_raw_fds(&sender, &[kept.as_raw_fd(), write_end.as_raw_fd()]);
+        drop(write_end);
+
+        let fd = receiver.recv_fd().expect("Failed to receive descriptor");
+        drop(unsafe { File::from_raw_fd(fd) });
+
+        // the second descriptor was the last write end, so it must have been closed
+        assert!(pipe_reaches_eof(read_end));
+    }
+
+    #[test]
+    fn test_recv_fd_closes_descriptors_of_truncated_message() {
+        let (sender, receiver) = Socket::pair().expect("Failed to create socket pair");
+        let (read_end, write_end) = pipe_pair();
+
+        // more than the FD_CONTROL_WORDS buffer holds
+        let fds = [write_end.as_raw_fd(); 8];
+        send_raw_fds(&sender, &fds);
+        drop(write_end);
+
+        assert_eq!(
+            receiver.recv_fd(),
+            Err(SocketError::InvalidInput(
+                "Message carried more file descriptors than fit"
+            ))
+        );
+        assert!(pipe_reaches_eof(read_end));
+    }
+
+    #[test]
+    fn test_recv_fd_without_descriptor() {
+        let (sender, receiver) = Socket::pair().expect("Failed to create socket pair");
+        sender.send(b"x").expect("Failed to send data");
+
+        assert_eq!(
+            receiver.recv_fd(),
+            Err(SocketError::InvalidInput(
+                "Message did not carry a file descriptor"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_send_fd_rejects_tcp_socket() {
+        let (client, _server) = connected_pair();
+        assert!(matches!(
+            client.send_fd(0),
+            Err(SocketError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_peer_credentials_match_current_process() {
+        let (first, _second) = Socket::pair().expect("Failed to create socket pair");
+        let cred = first
+            .peer_credentials()
+            .expect("Failed to read peer credentials");
+
+        assert_eq!(cred.uid, unsafe { libc::getuid() });
+        assert_eq!(cred.gid, unsafe { libc::getgid() });
+        assert_eq!(cred.pid, std::process::id() as i32);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_abstract_socket_round_trip_leaves_no_file() {
+        let name = format!("berkeley_sockets_abstract_{}", std::process::id());
+
+        let mut listener = Socket::new_unix().expect("Failed to create socket");
+        listener
+            .bind_abstract(&name)
+            .expect("Failed to bind abstract socket");
+        listener.listen(1).expect("Failed to listen");
+
+        let mut client = Socket::new_unix().expect("Failed to create socket");
+        client
+            .connect_abstract(&name)
+            .expect("Failed to connect to abstract socket");
+        let (server, _) = listener.accept().expect("Failed to accept connection");
+
+        client.send_all(b"hidden").expect("Failed to send data");
+        let mut buf = [0u8; 6];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"hidden");
+
+        assert!(!std::path::Path::new(&name).exists());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_abstract_local_addr_round_trips() {
+        let name = format!("berkeley_sockets_abstract_addr_{}", std::process::id());
+
+        let mut listener = Socket::new_unix().expect("Failed to create socket");
+        listener
+            .bind_abstract(&name)
+            .expect("Failed to bind abstract socket");
+        listener.listen(1).expect("Failed to listen");
+        let addr = listener.local_addr().expect("Failed to read local address");
+
+        let mut client = Socket::new_unix().expect("Failed to create socket");
+        client
+            .connect(&addr)
+            .expect("Failed to connect to the reported address");
+        drop(listener.accept().expect("Failed to accept connection"));
+        drop(client);
+        drop(listener);
+
+        // the name is free again once the listener is gone
+        let mut rebound = Socket::new_unix().expect("Failed to create socket");
+        rebound
+            .bind(&addr)
+            .expect("Failed to bind to the reported address");
+        assert_eq!(rebound.local_addr(), Ok(addr));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_mmsg_batches_datagrams() {
+        let mut receiver = Socket::new_udp().expect("Failed to create socket");
+        receiver
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&receiver);
+
+        let mut sender = Socket::new_udp().expect("Failed to create socket");
+        sender
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let sender_port = bound_port(&sender);
+
+        let payloads: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; i as usize + 1]).collect();
+        let msgs: Vec<(&[u8], (&str, u16))> = payloads
+            .iter()
+            .map(|payload| (payload.as_slice(), ("127.0.0.1", port)))
+            .collect();
+        assert_eq!(sender.send_mmsg(&msgs), Ok(8));
+
+        let mut storage = [[0u8; 16]; 8];
+        let mut bufs: Vec<&mut [u8]> = storage.iter_mut().map(|buf| &mut buf[..]).collect();
+        let received = receiver
+            .recv_mmsg(&mut bufs)
+            .expect("Failed to receive datagrams");
+
+        assert_eq!(received.len(), 8);
+        for (i, (len, from)) in received.iter().enumerate() {
+            let len = *len;
+            assert_eq!(len, i + 1);
+            assert_eq!(from, &Endpoint::V4(Ipv4Addr::LOCALHOST, sender_port));
+            assert_eq!(&bufs[i][..len], &payloads[i][..]);
+        }
+    }
+
+    #[test]
+    fn test_socket_type_matches_creation() {
+        let stream = Socket::new().expect("Failed to create socket");
+        let datagram = Socket::new_udp().expect("Failed to create socket");
+
+        assert_eq!(stream.socket_type(), Ok(SOCK_STREAM));
+        assert_eq!(datagram.socket_type(), Ok(SOCK_DGRAM));
+
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(stream.protocol(), Ok(IPPROTO_TCP));
+            assert_eq!(datagram.protocol(), Ok(libc::IPPROTO_UDP));
+        }
+    }
+
+    #[test]
+    fn test_is_listening_socket_follows_listen() {
+        let mut sock = Socket::new().expect("Failed to create socket");
+        assert_eq!(sock.is_listening_socket(), Ok(false));
+
+        sock.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        sock.listen(1).expect("Failed to listen");
+        assert_eq!(sock.is_listening_socket(), Ok(true));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_bind_to_loopback_device() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        match sock.bind_to_device("lo") {
+            Ok(()) => {}
+            // unprivileged processes may not be allowed to pick an interface
+            Err(SocketError::PermissionDenied) => return,
+            Err(err) => panic!("Failed to bind to lo: {}", err),
+        }
+
+        assert!(matches!(
+            sock.bind_to_device("an_interface_name_too_long"),
+            Err(SocketError::InvalidInput(_))
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_set_mark() {
+        let sock = Socket::new().expect("Failed to create socket");
+
+        match sock.set_mark(42) {
+            Ok(()) => {}
+            // marking packets needs CAP_NET_ADMIN
+            Err(SocketError::PermissionDenied) => return,
+            Err(err) => panic!("Failed to set SO_MARK: {}", err),
+        }
+
+        assert_eq!(sock.mark(), Ok(42));
+    }
+
+    #[test]
+    fn test_tos_round_trip() {
+        let sock = Socket::new_udp().expect("Failed to create socket");
+        // DSCP 46, expedited forwarding
+        let tos = 46 << 2;
+
+        sock.set_tos(tos).expect("Failed to set IP_TOS");
+        assert_eq!(sock.tos(), Ok(tos));
+    }
+
+    #[test]
+    fn test_addresses_of_ipv6_and_unix_sockets() {
+        let mut listener = Socket::new_v6().expect("Failed to create socket");
+        listener.bind(("::1", 0)).expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = bound_port(&listener);
+        assert_eq!(
+            listener.local_addr(),
+            Ok(Endpoint::V6(Ipv6Addr::LOCALHOST, port))
+        );
+
+        let mut client = Socket::new_v6().expect("Failed to create socket");
+        client.connect(("::1", port)).expect("Failed to connect");
+        let (_server, peer) = listener.accept().expect("Failed to accept connection");
+        assert_eq!(
+            peer,
+            client.local_addr().expect("Failed to read local address")
+        );
+
+        let path = temp_socket_path("endpoint");
+        let mut unix = Socket::new_unix().expect("Failed to create socket");
+        unix.bind_unix(&path).expect("Failed to bind socket");
+        assert_eq!(unix.local_addr(), Ok(Endpoint::Unix(PathBuf::from(&path))));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_bind_and_connect_with_std_addresses() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect((Ipv4Addr::LOCALHOST, port))
+            .expect("Failed to connect");
+        assert!(client.is_connected());
+
+        let mut v6 = Socket::new_v6().expect("Failed to create socket");
+        assert!(matches!(
+            v6.bind(std::net::SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+            Err(SocketError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_and_recv_oob() {
+        let (client, server) = connected_pair();
+
+        client.send_all(b"ab").expect("Failed to send data");
+        client.send_oob(b'!').expect("Failed to send urgent data");
+
+        // the urgent byte can trail the normal data, wait until it has been queued
+        let mut byte = Err(SocketError::WouldBlock);
+        for _ in 0..100 {
+            byte = server.recv_oob();
+            if byte.is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(byte.expect("Failed to receive urgent data"), b'!');
+
+        // the normal stream no longer contains the urgent byte
+        let mut buf = [0u8; 2];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"ab");
+    }
+
+    #[test]
+    fn test_set_oob_inline() {
+        let (client, server) = connected_pair();
+        server
+            .set_oob_inline(true)
+            .expect("Failed to set SO_OOBINLINE");
+        assert!(server.oob_inline().expect("Failed to get SO_OOBINLINE"));
+
+        client.send_all(b"ab").expect("Failed to send data");
+        client.send_oob(b'!').expect("Failed to send urgent data");
+
+        let mut buf = [0u8; 3];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"ab!");
+    }
+
+    #[test]
+    fn test_stats_count_bytes_sent_and_received() {
+        let (client, server) = connected_pair();
+
+        client.send_all(&[7u8; 100]).expect("Failed to send data");
+        let mut buf = [0u8; 100];
+        server.peek(&mut buf).expect("Failed to peek data");
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+
+        assert_eq!(client.stats().bytes_sent, 100);
+        assert_eq!(client.stats().bytes_received, 0);
+        assert_eq!(server.stats().bytes_received, 100);
+    }
+
+    #[test]
+    fn test_stats_counters_saturate() {
+        let counters = Counters::default();
+        counters.sent.store(u64::MAX - 1, Ordering::Relaxed);
+        Counters::add(&counters.sent, 10);
+        assert_eq!(counters.sent.load(Ordering::Relaxed), u64::MAX);
+    }
+
+    #[test]
+    fn test_sockaddr_builders_set_family_for_platform() {
+        let v4 = ipv4_sockaddr(Ipv4Addr::LOCALHOST, 80);
+        assert_eq!(v4.sin_family as i32, AF_INET);
+        assert_eq!(u16::from_be(v4.sin_port), 80);
+
+        let v6 = ipv6_sockaddr(Ipv6Addr::LOCALHOST, 80);
+        assert_eq!(v6.sin6_family as i32, AF_INET6);
+
+        let unix = unix_sockaddr("/tmp/family.sock").expect("Failed to build address");
+        assert_eq!(unix.sun_family as i32, AF_UNIX);
+
+        // the kernel rejects a family it doesn't recognize, so a successful bind proves the
+        // field has the width this platform expects
+        let mut socket = Socket::new().expect("Failed to create socket");
+        socket
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let mut socket = Socket::new_v6().expect("Failed to create socket");
+        socket.bind(("::1", 0)).expect("Failed to bind socket");
+    }
+
+    #[test]
+    fn test_recv_waitall_reads_two_writes_at_once() {
+        let (client, server) = connected_pair();
+
+        let writer = std::thread::spawn(move || {
+            client.send_all(&[1u8; 8]).expect("Failed to send data");
+            std::thread::sleep(Duration::from_millis(50));
+            client.send_all(&[2u8; 8]).expect("Failed to send data");
+            client
+        });
+
+        let mut buf = [0u8; 16];
+        let received = server
+            .recv_waitall(&mut buf)
+            .expect("Failed to receive data");
+        assert_eq!(received, 16);
+        assert_eq!(&buf[..8], &[1u8; 8]);
+        assert_eq!(&buf[8..], &[2u8; 8]);
+
+        writer.join().expect("Writer thread panicked");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_defer_accept_round_trip() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+
+        listener
+            .set_defer_accept(5)
+            .expect("Failed to set TCP_DEFER_ACCEPT");
+        assert!(
+            listener
+                .defer_accept()
+                .expect("Failed to get TCP_DEFER_ACCEPT")
+                >= 5
+        );
+
+        listener
+            .set_defer_accept(0)
+            .expect("Failed to clear TCP_DEFER_ACCEPT");
+        assert_eq!(listener.defer_accept(), Ok(0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fastopen_listener_round_trip() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .set_fastopen(16)
+            .expect("Failed to set TCP_FASTOPEN");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(16).expect("Failed to listen");
+
+        assert_eq!(listener.fastopen_queue_len(), Ok(16));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_connect_fastopen_delivers_initial_data() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .set_fastopen(16)
+            .expect("Failed to set TCP_FASTOPEN");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(16).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        let sent = client
+            .connect_fastopen(("127.0.0.1", port), b"hello")
+            .expect("Failed to connect with Fast Open");
+        assert_eq!(sent, 5);
+        assert!(client.is_connected());
+
+        let (server, _) = listener.accept().expect("Failed to accept");
+        let mut buf = [0u8; 5];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[test]
+    fn test_take_error_reports_refused_datagram() {
+        // grab a free port and close it again so nothing listens there
+        let mut closed = Socket::new_udp().expect("Failed to create socket");
+        closed
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&closed);
+        closed.close().expect("Failed to close socket");
+
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.connect(("127.0.0.1", port))
+            .expect("Failed to connect socket");
+        assert_eq!(sock.take_error(), Ok(None));
+
+        // the ICMP port unreachable comes back after send has already returned
+        sock.send(b"ping").expect("Failed to send datagram");
+        let mut err = None;
+        for _ in 0..100 {
+            err = sock.take_error().expect("Failed to read SO_ERROR");
+            if err.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(err, Some(SocketError::ConnectionRefused));
+
+        // reading the error cleared it
+        assert_eq!(sock.take_error(), Ok(None));
+    }
+
+    #[test]
+    fn test_serve_handles_exactly_max_connections() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(8).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let clients: Vec<_> = (0..3u8)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let mut client = Socket::new().expect("Failed to create socket");
+                    client
+                        .connect(("127.0.0.1", port))
+                        .expect("Failed to connect");
+                    client.send_all(&[i]).expect("Failed to send data");
+                })
+            })
+            .collect();
+
+        let mut received = Vec::new();
+        listener
+            .serve(Some(3), |conn| {
+                let mut byte = [0u8; 1];
+                conn.recv_exact(&mut byte).expect("Failed to receive data");
+                received.push(byte[0]);
+            })
+            .expect("Failed to serve connections");
+
+        for client in clients {
+            client.join().expect("Client thread panicked");
+        }
+        received.sort();
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_serve_requires_listening_socket() {
+        let sock = Socket::new().expect("Failed to create socket");
+        assert_eq!(
+            sock.serve(None, |_| {}),
+            Err(SocketError::InvalidState("Socket is not listening"))
+        );
+    }
+
+    #[test]
+    fn test_accept_caches_peer_address() {
+        let (client, server) = connected_pair();
+
+        let cached = server
+            .peer
+            .clone()
+            .expect("Accepted socket has no cached peer");
+        assert_eq!(
+            Ok(cached.clone()),
+            server.query_peer_addr(),
+            "Cached peer differs from getpeername"
+        );
+        assert_eq!(
+            cached,
+            client.local_addr().expect("Failed to get local address")
+        );
+        assert_eq!(server.peer_addr(), Ok(cached));
+    }
+
+    #[test]
+    fn test_multicast_interface_round_trip() {
+        let sock = Socket::new_udp().expect("Failed to create socket");
+        assert_eq!(sock.multicast_interface_v4(), Ok(Ipv4Addr::UNSPECIFIED));
+
+        sock.set_multicast_interface_v4(Ipv4Addr::LOCALHOST)
+            .expect("Failed to set IP_MULTICAST_IF");
+        assert_eq!(sock.multicast_interface_v4(), Ok(Ipv4Addr::LOCALHOST));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mtu_discover_and_path_mtu() {
+        let mut receiver = Socket::new_udp().expect("Failed to create socket");
+        receiver
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&receiver);
+
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.set_mtu_discover(MtuDiscover::Do)
+            .expect("Failed to set IP_MTU_DISCOVER");
+        assert!(sock.path_mtu().is_err(), "Read a path MTU without a peer");
+
+        sock.connect(("127.0.0.1", port))
+            .expect("Failed to connect socket");
+        let mtu = sock.path_mtu().expect("Failed to read IP_MTU");
+        assert!(mtu >= 576, "Path MTU {} is below the IPv4 minimum", mtu);
+    }
+
+    #[test]
+    fn test_framed_round_trip() {
+        let (client, server) = connected_pair();
+        let msg: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+
+        client.send_framed(&msg).expect("Failed to send frame");
+        client.send_framed(b"").expect("Failed to send empty frame");
+
+        assert_eq!(server.recv_framed(), Ok(msg));
+        assert_eq!(server.recv_framed(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_recv_framed_rejects_oversized_frame() {
+        let (client, mut server) = connected_pair();
+        server.set_max_frame_len(100);
+
+        client
+            .send_framed(&[0u8; 101])
+            .expect("Failed to send frame");
+
+        assert_eq!(
+            server.recv_framed(),
+            Err(SocketError::InvalidInput(
+                "Frame exceeds the maximum frame length"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_read_line_strips_line_ending() {
+        let (client, server) = connected_pair();
+
+        client
+            .send_all(b"GET /\r\nHost: a\nrest")
+            .expect("Failed to send data");
+
+        assert_eq!(server.read_line(64), Ok("GET /".to_string()));
+        assert_eq!(server.read_line(64), Ok("Host: a".to_string()));
+
+        // bytes after the last newline are left for the next recv
+        let mut rest = [0u8; 4];
+        server
+            .recv_exact(&mut rest)
+            .expect("Failed to receive data");
+        assert_eq!(&rest, b"rest");
+    }
+
+    #[test]
+    fn test_read_line_rejects_long_and_invalid_lines() {
+        let (client, server) = connected_pair();
+
+        client.send_all(b"abcdef\n").expect("Failed to send data");
+        assert_eq!(
+            server.read_line(5),
+            Err(SocketError::InvalidInput("Line exceeds the maximum length"))
+        );
+
+        let (client, server) = connected_pair();
+        client.send_all(b"\xff\xfe\n").expect("Failed to send data");
+        assert_eq!(
+            server.read_line(5),
+            Err(SocketError::InvalidInput("Line is not valid UTF-8"))
+        );
+    }
+
+    #[test]
+    fn test_connected_udp_send_recv_and_disconnect() {
+        let mut peer = Socket::new_udp().expect("Failed to create socket");
+        peer.bind(("127.0.0.1", 0)).expect("Failed to bind socket");
+        let peer_port = bound_port(&peer);
+
+        let mut sock = Socket::new_udp().expect("Failed to create socket");
+        sock.connect(("127.0.0.1", peer_port))
+            .expect("Failed to connect socket");
+        assert!(sock.is_connected());
+        let local = sock.local_addr().expect("Failed to get local address");
+
+        sock.send(b"ping").expect("Failed to send datagram");
+        let mut buf = [0u8; 16];
+        let (len, from) = peer
+            .recv_from(&mut buf)
+            .expect("Failed to receive datagram");
+        assert_eq!(&buf[..len], b"ping");
+        assert_eq!(from, local);
+
+        peer.send_to(b"pong", &from)
+            .expect("Failed to send datagram");
+        let len = sock.recv(&mut buf).expect("Failed to receive datagram");
+        assert_eq!(&buf[..len], b"pong");
+
+        sock.disconnect().expect("Failed to disconnect socket");
+        assert!(!sock.is_connected());
+        assert_eq!(
+            sock.send(b"ping"),
+            Err(SocketError::InvalidState("Socket is not connected"))
+        );
+        sock.send_to(b"again", ("127.0.0.1", peer_port))
+            .expect("Failed to send datagram");
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_recv_from_with_info_reports_destination() {
+        let mut receiver = Socket::new_udp().expect("Failed to create socket");
+        receiver
+            .bind(("0.0.0.0", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&receiver);
+
+        let sender = Socket::new_udp().expect("Failed to create socket");
+        let mut buf = [0u8; 16];
+
+        // without the option the destination stays unknown
+        sender
+            .send_to(b"one", ("127.0.0.1", port))
+            .expect("Failed to send datagram");
+        let (_, _, dst) = receiver
+            .recv_from_with_info(&mut buf)
+            .expect("Failed to receive datagram");
+        assert_eq!(dst, None);
+
+        receiver
+            .set_recv_dst_addr(true)
+            .expect("Failed to set IP_PKTINFO");
+        sender
+            .send_to(b"two", ("127.0.0.1", port))
+            .expect("Failed to send datagram");
+        let (len, src, dst) = receiver
+            .recv_from_with_info(&mut buf)
+            .expect("Failed to receive datagram");
+
+        assert_eq!(&buf[..len], b"two");
+        assert_eq!(src.ip(), Some(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert_eq!(dst, Some(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn test_split_reads_and_writes_from_different_threads() {
+        use std::io::{BufRead, BufReader, BufWriter, Write};
+
+        let (client, server) = connected_pair();
+        let (read_half, write_half) = client.split().expect("Failed to split socket");
+
+        // echo every byte until the client's write half shuts down
+        let echo = std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            loop {
+                let n = server.recv(&mut buf).expect("Failed to receive data");
+                if n == 0 {
+                    break;
+                }
+                server.send_all(&buf[..n]).expect("Failed to send data");
+            }
+        });
+
+        let reader = std::thread::spawn(move || {
+            BufReader::new(read_half)
+                .lines()
+                .map(|line| line.expect("Failed to read line"))
+                .collect::<Vec<_>>()
+        });
+
+        let mut writer = BufWriter::new(write_half);
+        writer
+            .write_all(b"one\ntwo\n")
+            .expect("Failed to write data");
+        writer.flush().expect("Failed to flush data");
+        drop(writer);
+
+        echo.join().expect("Echo thread panicked");
+        assert_eq!(
+            reader.join().expect("Reader thread panicked"),
+            vec!["one".to_string(), "two".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_nonblocking_query_follows_descriptor() {
+        let sock = Socket::new().expect("Failed to create socket");
+        assert_eq!(sock.nonblocking(), Ok(false));
+
+        sock.set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+        assert_eq!(sock.nonblocking(), Ok(true));
+
+        // the mode survives a round trip through a raw descriptor
+        let sock = unsafe { Socket::from_raw_fd(sock.into_raw_fd()) };
+        assert_eq!(sock.nonblocking(), Ok(true));
+    }
+
+    #[test]
+    fn test_connect_retry_waits_for_late_listener() {
+        // bound but not yet listening, so connects are refused until the thread calls listen
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&listener);
+
+        let late = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            listener.listen(1).expect("Failed to listen");
+            listener.accept().expect("Failed to accept connection")
+        });
+
+        let started = Instant::now();
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect_retry(("127.0.0.1", port), 6, Duration::from_millis(50))
+            .expect("Failed to connect after retrying");
+
+        // the first two attempts at 0ms and 50ms came before the listener was ready
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(client.is_connected());
+        late.join().expect("Listener thread panicked");
+    }
+
+    #[test]
+    fn test_connect_retry_returns_last_error() {
+        let mut closed = Socket::new().expect("Failed to create socket");
+        closed
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        let port = bound_port(&closed);
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        assert_eq!(
+            client.connect_retry(("127.0.0.1", port), 0, Duration::from_millis(10)),
+            Err(SocketError::ConnectionRefused)
+        );
+    }
+
+    #[test]
+    fn test_interleave_families_alternates() {
+        let v4 = |n| Endpoint::V4(Ipv4Addr::new(10, 0, 0, n), 80);
+        let v6 = |n| Endpoint::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, n), 80);
+
+        assert_eq!(
+            interleave_families(vec![v6(1), v6(2), v6(3), v4(1)]),
+            vec![v6(1), v4(1), v6(2), v6(3)]
+        );
+        assert_eq!(
+            interleave_families(vec![v4(1), v4(2), v6(1)]),
+            vec![v4(1), v6(1), v4(2)]
+        );
+    }
+
+    #[test]
+    fn test_connect_happy_eyeballs_to_localhost() {
+        // only IPv4 listens, so an IPv6 attempt for localhost, if any, is refused and the
+        // IPv4 one has to win
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(4).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect_happy_eyeballs("localhost", port, Duration::from_secs(5))
+            .expect("Failed to connect to localhost");
+
+        assert!(client.is_connected());
+        assert_eq!(client.nonblocking(), Ok(false));
+        let peer = client.peer_addr().expect("Failed to get peer address");
+        assert_eq!(peer.ip(), Some(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn test_shutdown_handle_stops_serve() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(4).expect("Failed to listen");
+        let port = bound_port(&listener);
+        let handle = listener
+            .shutdown_handle()
+            .expect("Failed to create shutdown handle");
+
+        let server = std::thread::spawn(move || {
+            let mut served = 0;
+            let res = listener.serve(None, |_| served += 1);
+            (res, served)
+        });
+
+        // one connection is served normally before the loop is told to stop
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+        std::thread::sleep(Duration::from_millis(50));
+
+        handle.shutdown().expect("Failed to signal shutdown");
+        handle.shutdown().expect("Failed to signal shutdown twice");
+
+        let (res, served) = server.join().expect("Server thread panicked");
+        assert_eq!(res, Ok(()));
+        assert_eq!(served, 1);
+    }
+
+    #[test]
+    fn test_accept_timeout() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(1).expect("Failed to listen");
+        let port = bound_port(&listener);
+
+        let start = Instant::now();
+        let res = listener.accept_timeout(Duration::from_millis(100));
+        assert!(matches!(res, Ok(None)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let mut client = Socket::new().expect("Failed to create socket");
+        client
+            .connect(("127.0.0.1", port))
+            .expect("Failed to connect");
+
+        let accepted = listener
+            .accept_timeout(Duration::from_secs(5))
+            .expect("Failed to accept")
+            .expect("Timed out waiting for the client");
+        assert_eq!(accepted.peer_addr(), client.local_addr());
+    }
+
+    #[test]
+    fn test_accept_timeout_requires_listener() {
+        let socket = Socket::new().expect("Failed to create socket");
+        assert!(matches!(
+            socket.accept_timeout(Duration::from_millis(10)),
+            Err(SocketError::InvalidState("Socket is not listening"))
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_congestion_round_trip() {
+        let socket = Socket::new().expect("Failed to create socket");
+        let default = socket.congestion().expect("Failed to read TCP_CONGESTION");
+        assert!(!default.is_empty());
+
+        // setting the algorithm already in use needs no extra privileges
+        socket
+            .set_congestion(&default)
+            .expect("Failed to set TCP_CONGESTION");
+        assert_eq!(socket.congestion(), Ok(default));
+
+        assert_eq!(
+            socket.set_congestion("no-such-algo"),
+            Err(SocketError::InvalidInput(
+                "Congestion control algorithm is not available"
+            ))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_tcp_info_on_loopback() {
+        let (client, server) = connected_pair();
+
+        // an acked round trip gives the kernel an rtt sample
+        client.send(b"ping").expect("Failed to send");
+        let mut buf = [0u8; 4];
+        server.recv(&mut buf).expect("Failed to receive");
+        server.send(b"pong").expect("Failed to send");
+        client.recv(&mut buf).expect("Failed to receive");
+
+        let info = client.tcp_info().expect("Failed to read TCP_INFO");
+        // TCP_ESTABLISHED
+        assert_eq!(info.state, 1);
+        assert!(info.rtt > Duration::ZERO);
+        assert!(info.rtt < Duration::from_secs(1));
+        assert!(info.snd_cwnd > 0);
+    }
+
+    #[test]
+    fn test_nonblocking_recv_tells_close_from_no_data() {
+        let (client, server) = connected_pair();
+        server
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+        let mut buf = [0u8; 16];
+
+        assert_eq!(server.recv(&mut buf), Err(SocketError::WouldBlock));
+
+        client.send(b"hello").expect("Failed to send data");
+        assert!(server
+            .poll_readable(Some(Duration::from_secs(5)))
+            .expect("Failed to poll"));
+        assert_eq!(server.recv(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"hello");
+
+        // drained but still open
+        assert_eq!(server.recv(&mut buf), Err(SocketError::WouldBlock));
+
+        drop(client);
+        assert!(server
+            .poll_readable(Some(Duration::from_secs(5)))
+            .expect("Failed to poll"));
+        assert_eq!(server.recv(&mut buf), Ok(0));
+        assert_eq!(server.recv(&mut buf), Ok(0));
+    }
+
+    #[test]
+    fn test_read_reports_close_as_zero_and_empty_as_would_block() {
+        use std::io::Read;
+
+        let (client, mut server) = connected_pair();
+        server
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+        let mut buf = [0u8; 16];
+
+        let err = server.read(&mut buf).expect_err("Read should not succeed");
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop(client);
+        assert!(server
+            .poll_readable(Some(Duration::from_secs(5)))
+            .expect("Failed to poll"));
+        assert_eq!(server.read(&mut buf).expect("Failed to read"), 0);
+    }
+
+    #[test]
+    fn test_redirect_to_spare_fd() {
+        let (first, second) = Socket::pair().expect("Failed to create socket pair");
+
+        // another socket's descriptor stands in for stdin or stdout, dup2 replaces it
+        let spare = Socket::new_udp()
+            .expect("Failed to create socket")
+            .into_raw_fd();
+        first.redirect_to(spare).expect("Failed to redirect socket");
+
+        // unlike the socket's own descriptor the target survives an exec
+        assert_eq!(unsafe { fcntl(spare, F_GETFD) } & FD_CLOEXEC, 0);
+
+        let redirected = unsafe { Socket::from_raw_fd(spare) };
+        assert_eq!(redirected.socket_type(), Ok(SOCK_STREAM));
+
+        second.send(b"hello").expect("Failed to send data");
+        let mut buf = [0u8; 5];
+        redirected
+            .recv_exact(&mut buf)
+            .expect("Failed to read through the redirected descriptor");
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[test]
+    fn test_recv_lowat_delays_readability() {
+        let (client, server) = connected_pair();
+        server.set_recv_lowat(8).expect("Failed to set SO_RCVLOWAT");
+        assert_eq!(server.recv_lowat(), Ok(8));
+
+        client.send(b"abcd").expect("Failed to send data");
+        assert_eq!(
+            server.poll_readable(Some(Duration::from_millis(100))),
+            Ok(false)
+        );
+
+        client.send(b"efgh").expect("Failed to send data");
+        assert_eq!(server.poll_readable(Some(Duration::from_secs(5))), Ok(true));
+
+        let mut buf = [0u8; 8];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"abcdefgh");
+    }
+
+    #[test]
+    fn test_tcp_stream_interop() {
+        use std::io::Write;
+
+        let (client, server) = connected_pair();
+        let client_addr = client.local_addr().expect("Failed to read local address");
+
+        let mut stream = client
+            .into_tcp_stream()
+            .expect("Failed to convert to TcpStream");
+        assert_eq!(
+            stream.local_addr().map(Endpoint::from).ok(),
+            Some(client_addr)
+        );
+        stream
+            .write_all(b"hello")
+            .expect("Failed to write to TcpStream");
+
+        let mut buf = [0u8; 5];
+        server.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"hello");
+
+        let client = Socket::from_tcp_stream(stream);
+        assert!(client.is_connected());
+        server.send(b"world").expect("Failed to send data");
+        client.recv_exact(&mut buf).expect("Failed to receive data");
+        assert_eq!(&buf, b"world");
+
+        let udp = Socket::new_udp().expect("Failed to create socket");
+        assert!(matches!(
+            udp.into_tcp_stream(),
+            Err(SocketError::InvalidInput("Socket is not a TCP socket"))
+        ));
+    }
+
+    #[test]
+    fn test_read_keeps_the_socket_error() {
+        use std::io::Read;
+
+        let (_client, mut server) = connected_pair();
+        server
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .expect("Failed to set read timeout");
+
+        let mut buf = [0u8; 16];
+        let err = server.read(&mut buf).expect_err("Read should time out");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_redirect_to_own_descriptor() {
+        let (first, second) = Socket::pair().expect("Failed to create socket pair");
+        let fd = first.as_raw_fd();
+
+        first.redirect_to(fd).expect("Failed to redirect socket");
+        assert_eq!(unsafe { fcntl(fd, F_GETFD) } & FD_CLOEXEC, 0);
+
+        // still open and now owned by the Socket adopting it again
+        let redirected = unsafe { Socket::from_raw_fd(fd) };
+        second.send(b"hi").expect("Failed to send data");
+        let mut buf = [0u8; 2];
+        redirected
+            .recv_exact(&mut buf)
+            .expect("Failed to receive data");
+        assert_eq!(&buf, b"hi");
+    }
+
+    #[test]
+    fn test_shutdown_handle_interrupts_blocked_accept() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(4).expect("Failed to listen");
+        let handle = listener
+            .shutdown_handle()
+            .expect("Failed to create shutdown handle");
+        let listener = Arc::new(listener);
+
+        let blocked = {
+            let listener = listener.clone();
+            std::thread::spawn(move || listener.accept().map(|_| ()))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        handle.shutdown().expect("Failed to signal shutdown");
+
+        let shut_down = Err(SocketError::Shutdown);
+        assert_eq!(blocked.join().expect("Accept thread panicked"), shut_down);
+
+        // the shutdown is permanent
+        assert_eq!(
+            listener.accept_timeout(Duration::from_secs(5)).map(|_| ()),
+            shut_down
+        );
+        assert_eq!(listener.serve(None, |_| {}), Ok(()));
+    }
+
+    #[test]
+    fn test_shutdown_handle_keeps_accept_modes() {
+        let mut listener = Socket::new().expect("Failed to create socket");
+        listener
+            .bind(("127.0.0.1", 0))
+            .expect("Failed to bind socket");
+        listener.listen(4).expect("Failed to listen");
+        let _handle = listener
+            .shutdown_handle()
+            .expect("Failed to create shutdown handle");
+
+        listener
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .expect("Failed to set read timeout");
+        assert!(matches!(listener.accept(), Err(SocketError::TimedOut)));
+        assert!(matches!(
+            listener.accept_timeout(Duration::from_millis(10)),
+            Ok(None)
+        ));
+
+        listener
+            .set_nonblocking(true)
+            .expect("Failed to set non-blocking mode");
+        assert!(matches!(listener.accept(), Err(SocketError::WouldBlock)));
+    }
+
+    #[test]
+    fn test_from_raw_fd_keeps_close_on_exec_flag() {
+        let sock = Socket::new().expect("Failed to create socket");
+        let fd = sock.into_raw_fd();
+        assert_ne!(unsafe { fcntl(fd, F_SETFD, 0) }, -1);
+
+        let adopted = unsafe { Socket::from_raw_fd(fd) };
+        assert_eq!(
+            unsafe { fcntl(adopted.as_raw_fd(), F_GETFD) } & FD_CLOEXEC,
+            0
+        );
     }
 }